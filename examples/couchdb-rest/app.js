@@ -2,6 +2,9 @@ app.interface = '127.0.0.1';
 app.port = 8000;
 app.host = 'localhost';
 
+// CouchDB runs on this machine, and loopback addresses are denied by default
+app.deniedAddresses = [];
+
 // TODO: Need an optional trailing slash
 app.get('/users', 'list.js', policy => {
   policy.outboundHttp.allowGet('http://localhost:5984/users/_all_docs');
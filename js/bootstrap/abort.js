@@ -0,0 +1,82 @@
+const { getPrivate } = self._bindings;
+
+const abortedSym = getPrivate('aborted');
+const listenersSym = getPrivate('listeners');
+const signalSym = getPrivate('signal');
+
+// Signals can only be created by controllers
+const constructKey = Symbol('constructKey');
+
+export class AbortSignal {
+  constructor(key) {
+    if (key !== constructKey) {
+      throw new TypeError('Illegal constructor');
+    }
+    this[abortedSym] = false;
+    this[listenersSym] = [];
+    this.onabort = null;
+  }
+
+  get aborted() {
+    return this[abortedSym];
+  }
+
+  addEventListener(type, listener) {
+    if (type === 'abort' && typeof listener === 'function' &&
+        !this[listenersSym].includes(listener)) {
+      this[listenersSym].push(listener);
+    }
+  }
+
+  removeEventListener(type, listener) {
+    if (type === 'abort') {
+      this[listenersSym] = this[listenersSym].filter(l => l !== listener);
+    }
+  }
+}
+
+function abortSignal(signal) {
+  if (signal[abortedSym]) {
+    return;
+  }
+  signal[abortedSym] = true;
+  const event = { type: 'abort', target: signal };
+  const listeners = [signal.onabort, ...signal[listenersSym]];
+  for (const listener of listeners) {
+    if (typeof listener === 'function') {
+      try {
+        listener.call(signal, event);
+      } catch (e) {
+        console.error(e.stack);
+      }
+    }
+  }
+}
+
+export class AbortController {
+  constructor() {
+    this[signalSym] = new AbortSignal(constructKey);
+  }
+
+  get signal() {
+    return this[signalSym];
+  }
+
+  abort() {
+    abortSignal(this[signalSym]);
+  }
+}
+
+export function abortError() {
+  const error = new Error('The operation was aborted');
+  error.name = 'AbortError';
+  return error;
+}
+
+// Fetches which take longer than their route's outbound timeout reject with
+// this, like a fetch given `AbortSignal.timeout()` would
+export function timeoutError(message) {
+  const error = new Error(message);
+  error.name = 'TimeoutError';
+  return error;
+}
@@ -0,0 +1,77 @@
+import { isBufferish, toArrayBuffer } from 'internal:common.js';
+
+const { getPrivate } = self._bindings;
+
+const bytesSym = getPrivate('bytes');
+const typeSym = getPrivate('type');
+
+// https://w3c.github.io/FileAPI/#blob-section
+// The bytes are held in memory, so a Blob is only as large as the parts it was
+// made from.
+export default class Blob {
+  // #bytes;
+  // #type;
+  constructor(parts = [], options = {}) {
+    const arrays = [];
+    let size = 0;
+    for (let part of parts) {
+      if (part instanceof Blob) {
+        part = part[bytesSym];
+      } else {
+        if (typeof part !== 'string' && !isBufferish(part)) {
+          part = String(part);
+        }
+        part = new Uint8Array(toArrayBuffer(part));
+      }
+      arrays.push(part);
+      size += part.byteLength;
+    }
+    const bytes = new Uint8Array(size);
+    let offset = 0;
+    for (const array of arrays) {
+      bytes.set(array, offset);
+      offset += array.byteLength;
+    }
+    this[bytesSym] = bytes;
+    const type = typeof options.type === 'string' ? options.type : '';
+    // Types with characters outside of printable ASCII are ignored
+    this[typeSym] = /^[\x20-\x7e]*$/.test(type) ? type.toLowerCase() : '';
+  }
+
+  get size() {
+    return this[bytesSym].byteLength;
+  }
+
+  get type() {
+    return this[typeSym];
+  }
+
+  slice(start = 0, end = this.size, type = '') {
+    const blob = new Blob([], { type });
+    blob[bytesSym] = this[bytesSym].slice(start, end);
+    return blob;
+  }
+
+  async arrayBuffer() {
+    return blobBytes(this);
+  }
+
+  async text() {
+    return new TextDecoder().decode(this[bytesSym]);
+  }
+
+  stream() {
+    const bytes = this[bytesSym];
+    return new ReadableStream({
+      start(controller) {
+        controller.enqueue(bytes.slice());
+        controller.close();
+      }
+    });
+  }
+}
+
+// A copy of the Blob's bytes, for sending it as a body
+export function blobBytes(blob) {
+  return blob[bytesSym].slice().buffer;
+}
@@ -6,6 +6,9 @@ export default class BodyMixin {
     const lengths = [];
     let totalLength = 0;
     for await (let buf of this.body) {
+      if (!isBufferish(buf)) {
+        buf = String(buf);
+      }
       if (typeof buf === 'string') {
         const encoder = new TextEncoder();
         buf = encoder.encode(buf).buffer;
@@ -24,20 +27,11 @@ export default class BodyMixin {
     return result.buffer;
   }
 
+  // The whole body is decoded at once, since a character may be split across
+  // chunks
   async text() {
-    let result = '';
-    for await (let chunk of this.body) {
-      if (typeof chunk === 'object' && chunk !== null && isBufferish(chunk)) {
-        const decoder = new TextDecoder();
-        chunk = decoder.decode(chunk);
-      }
-      if (typeof chunk === 'string') {
-        result += chunk;
-      } else {
-        result += String(chunk);
-      }
-    }
-    return result;
+    const decoder = new TextDecoder();
+    return decoder.decode(await this.arrayBuffer());
   }
 
   async json() {
@@ -6,6 +6,23 @@ export function isBufferish(chunk) {
     (chunk.buffer && chunk.buffer instanceof ArrayBuffer);
 }
 
+// Copies a body chunk into an ArrayBuffer of its own, which is how chunks are
+// handed to Rust. Views only contribute the part of their buffer they cover.
+export function toArrayBuffer(chunk) {
+  if (chunk instanceof ArrayBuffer) {
+    return chunk;
+  }
+  if (typeof chunk === 'string') {
+    chunk = new TextEncoder().encode(chunk);
+  }
+  if (isBufferish(chunk)) {
+    return chunk.buffer.slice(chunk.byteOffset, chunk.byteOffset + chunk.byteLength);
+  }
+  throw new TypeError(
+    'body chunks must be strings, ArrayBuffers, TypedArrays or DataViews'
+  );
+}
+
 export function unimplemented() {
   throw new Error('Unimplemented!');
 }
@@ -0,0 +1,116 @@
+const { getPrivate, signCookie, verifyCookie } = self._bindings;
+
+const valuesSym = getPrivate('values');
+const outgoingSym = getPrivate('outgoing');
+const closedSym = getPrivate('closed');
+
+// https://tools.ietf.org/html/rfc6265#section-4.1.1
+const cookieNameRe = /^[!#$%&'*+\-.^_`|~0-9a-zA-Z]+$/;
+const attributeValueRe = /^[^\x00-\x1f\x7f;]*$/;
+const sameSiteValues = { strict: 'Strict', lax: 'Lax', none: 'None' };
+
+// The cookies sent with the inbound request, which are parsed in native code,
+// along with the cookies to set on the response.
+export default class Cookies {
+  // #values;
+  // #outgoing;
+  // #closed; // why cookies can no longer be set, if they can't
+  constructor(values) {
+    this[valuesSym] = values;
+    this[outgoingSym] = [];
+    this[closedSym] = null;
+  }
+
+  // Signed cookies whose signature doesn't match are treated as missing
+  get(name, options = {}) {
+    if (!Object.prototype.hasOwnProperty.call(this[valuesSym], name)) {
+      return undefined;
+    }
+    const value = this[valuesSym][name];
+    if (!options.signed) {
+      return value;
+    }
+    const verified = verifyCookie(name, value);
+    if (verified === undefined) {
+      throw new Error('Signed cookies require app.cookieSecret to be set');
+    }
+    return verified === null ? undefined : verified;
+  }
+
+  getAll() {
+    return { ...this[valuesSym] };
+  }
+
+  // https://tools.ietf.org/html/rfc6265#section-4.1
+  set(name, value, options = {}) {
+    if (this[closedSym]) {
+      throw new Error(this[closedSym]);
+    }
+    name = String(name);
+    if (!cookieNameRe.test(name)) {
+      throw new TypeError('Invalid cookie name: ' + name);
+    }
+    value = String(value);
+    if (options.signed) {
+      value = signCookie(name, value);
+      if (value === undefined) {
+        throw new Error('Signed cookies require app.cookieSecret to be set');
+      }
+    }
+    let cookie = `${name}=${encodeURIComponent(value)}`;
+    if (options.maxAge !== undefined) {
+      if (!Number.isInteger(options.maxAge)) {
+        throw new TypeError('maxAge must be an integer number of seconds');
+      }
+      cookie += `; Max-Age=${options.maxAge}`;
+    }
+    if (options.domain !== undefined) {
+      cookie += `; Domain=${attributeValue('domain', options.domain)}`;
+    }
+    if (options.path !== undefined) {
+      cookie += `; Path=${attributeValue('path', options.path)}`;
+    }
+    if (options.secure) {
+      cookie += '; Secure';
+    }
+    if (options.httpOnly) {
+      cookie += '; HttpOnly';
+    }
+    if (options.sameSite !== undefined) {
+      const sameSite = sameSiteValues[String(options.sameSite).toLowerCase()];
+      if (!sameSite) {
+        throw new TypeError('sameSite must be one of "strict", "lax" or "none"');
+      }
+      cookie += `; SameSite=${sameSite}`;
+    }
+    this[outgoingSym].push(cookie);
+  }
+
+  // Expires the cookie, which must be given the same path and domain it was
+  // set with
+  delete(name, options = {}) {
+    const { path, domain } = options;
+    this.set(name, '', { path, domain, maxAge: 0 });
+  }
+}
+
+function attributeValue(attribute, value) {
+  value = String(value);
+  if (!attributeValueRe.test(value)) {
+    throw new TypeError(`Invalid cookie ${attribute}: ${value}`);
+  }
+  return value;
+}
+
+// The Set-Cookie headers for the cookies which were set. Once they've been
+// taken for the response, setting more cookies throws.
+export function outgoingCookies(cookies) {
+  closeCookies(cookies, 'Cookies can only be set before the response is sent');
+  return cookies[outgoingSym];
+}
+
+// Makes setting cookies throw the given message, e.g. when there's no response
+// to add them to
+export function closeCookies(cookies, message) {
+  cookies[closedSym] = message;
+}
@@ -0,0 +1,115 @@
+import Headers from 'internal:headers.js';
+import Response from 'internal:response.js';
+
+const { getPrivate, setEventStreamHandler, watchEventStream } = self._bindings;
+
+const heartbeatSym = getPrivate('heartbeat');
+const controllerSym = getPrivate('controller');
+const readableSym = getPrivate('readable');
+const closedSym = getPrivate('closed');
+const resolveClosedSym = getPrivate('resolveClosed');
+
+const DEFAULT_HEARTBEAT = 15000;
+
+const streams = new Map();
+
+// A response which sends Server-Sent Events. The worker returns it, and then
+// keeps sending events until either it calls `close()`, or the client
+// disconnects, which is signaled through `onclose` and the `closed` promise.
+export default class EventStream {
+  constructor({ heartbeat = DEFAULT_HEARTBEAT } = {}) {
+    if (!Number.isInteger(heartbeat) || heartbeat < 0) {
+      throw new TypeError('heartbeat must be a non-negative number of milliseconds');
+    }
+    this[heartbeatSym] = heartbeat;
+    this[closedSym] = false;
+    this[readableSym] = new ReadableStream({
+      start: controller => {
+        this[controllerSym] = controller;
+      }
+    });
+    this.closed = new Promise(resolve => {
+      this[resolveClosedSym] = resolve;
+    });
+    this.onclose = null;
+  }
+
+  // Sends an event, which is either the data, or an object with `data` and
+  // optionally `event`, `id` and `retry`. Data which isn't a string is sent as
+  // JSON. Returns false if the stream has already been closed.
+  send(event) {
+    if (this[closedSym]) {
+      return false;
+    }
+    if (typeof event !== 'object' || event === null) {
+      event = { data: event };
+    }
+    let message = '';
+    if (event.event !== undefined) {
+      message += `event: ${singleLine(event.event)}\n`;
+    }
+    if (event.id !== undefined) {
+      message += `id: ${singleLine(event.id)}\n`;
+    }
+    if (event.retry !== undefined) {
+      if (!Number.isInteger(event.retry) || event.retry < 0) {
+        throw new TypeError('retry must be a non-negative number of milliseconds');
+      }
+      message += `retry: ${event.retry}\n`;
+    }
+    const data = typeof event.data === 'string' ? event.data : JSON.stringify(event.data);
+    for (const line of String(data).split(/\r\n|\r|\n/)) {
+      message += `data: ${line}\n`;
+    }
+    this[controllerSym].enqueue(message + '\n');
+    return true;
+  }
+
+  close() {
+    if (this[closedSym]) {
+      return;
+    }
+    this[closedSym] = true;
+    this[controllerSym].close();
+    if (typeof this.onclose === 'function') {
+      try {
+        this.onclose();
+      } catch (e) {
+        console.error(e.stack);
+      }
+    }
+    this[resolveClosedSym]();
+  }
+}
+
+function singleLine(value) {
+  const string = String(value);
+  if (/[\r\n]/.test(string)) {
+    throw new TypeError('event names and ids must not contain newlines');
+  }
+  return string;
+}
+
+export function eventStreamResponse(stream) {
+  return new Response(stream[readableSym], {
+    headers: new Headers({
+      'Content-Type': 'text/event-stream',
+      'Cache-Control': 'no-cache',
+      'X-Accel-Buffering': 'no'
+    })
+  });
+}
+
+// Called once the response has been started
+export function watchEventStreamResponse(reqId, stream) {
+  streams.set(reqId, stream);
+  stream.closed.then(() => streams.delete(reqId));
+  watchEventStream(reqId, stream[heartbeatSym]);
+}
+
+setEventStreamHandler(function handleDisconnect(reqId) {
+  const stream = streams.get(reqId);
+  if (stream) {
+    stream.close();
+  }
+});
@@ -1,18 +1,33 @@
 import FormData from 'internal:form_data.js';
-import Response from 'internal:response.js';
+import { fetchResponse } from 'internal:response.js';
 import Request from 'internal:request.js';
+import { abortError, timeoutError } from 'internal:abort.js';
+import { toArrayBuffer } from 'internal:common.js';
 
 const {
   setFetchHandler,
-  _fetch
+  setFetchDrainHandler,
+  _fetch,
+  abortFetch
 } = self._bindings;
 
 const fetchCbs = {};
-function handleFetch(err, body, meta, fetchId) {
-  fetchCbs[fetchId](err, body, meta);
+function handleFetch(err, body, meta, fetchId, errName) {
+  fetchCbs[fetchId](err, body, meta, errName);
 }
 setFetchHandler(handleFetch);
 
+// Resolves the writes which are waiting for room in their request's body
+const drainWaiters = {};
+function handleFetchDrain(fetchId) {
+  const resolve = drainWaiters[fetchId];
+  if (resolve) {
+    delete drainWaiters[fetchId];
+    resolve();
+  }
+}
+setFetchDrainHandler(handleFetchDrain);
+
 // https://tools.ietf.org/html/rfc1867
 // https://www.w3.org/Protocols/rfc1341/7_2_Multipart.html#z0
 function generateMultipartFormData(formData) {
@@ -41,14 +56,40 @@ function generateMultipartFormData(formData) {
 
 let increasingFetchId = 0;
 
-export default async function fetch(input, init) {
+export default async function fetch(input, init = {}) {
   const fetchId = ++increasingFetchId;
+  const signal = init.signal || (input instanceof Request ? input.signal : undefined);
+  if (signal && signal.aborted) {
+    throw abortError();
+  }
   const p = new Promise((resolve, reject) => {
     let writer = null;
-    fetchCbs[fetchId] = (err, data, meta) => {
+    const onAbort = () => {
+      abortFetch(fetchId);
+      delete fetchCbs[fetchId];
+      const error = abortError();
+      if (writer) {
+        writer.error(error);
+      } else {
+        reject(error);
+      }
+    };
+    if (signal) {
+      signal.addEventListener('abort', onAbort);
+    }
+    fetchCbs[fetchId] = (err, data, meta, errName) => {
       if (err) {
-        err = new Error(err);
-        reject(err);
+        if (signal) {
+          signal.removeEventListener('abort', onAbort);
+        }
+        delete fetchCbs[fetchId];
+        err = errName === 'TimeoutError' ? timeoutError(err) : new Error(err);
+        if (writer) {
+          // The response body was still being received
+          writer.error(err);
+        } else {
+          reject(err);
+        }
         console.error('rejected fetch call due to: ' + err);
         return;
       }
@@ -58,8 +99,11 @@ export default async function fetch(input, init) {
             writer = controller;
           }
         });
-        resolve(new Response(readable, meta));
+        resolve(fetchResponse(readable, meta));
       } else if (data === null) {
+        if (signal) {
+          signal.removeEventListener('abort', onAbort);
+        }
         writer.close();
         delete fetchCbs[fetchId];
       } else {
@@ -72,7 +116,7 @@ export default async function fetch(input, init) {
   });
 
   if (typeof input === 'string') {
-    input = new Request(input, init);
+    input = new Request(input, { ...init, signal });
   }
 
   const url = input.url;
@@ -81,23 +125,41 @@ export default async function fetch(input, init) {
   }
   const headers = input.headers;
   const method = input.method.toUpperCase();
+  const redirect = input.redirect;
 
   if (typeof input._bodyString === 'string') {
-    _fetch(url, headers, method, input._bodyString, fetchId, 'string');
+    _fetch(url, headers, method, input._bodyString, fetchId, 'string', redirect);
+  } else if (input._bodyBytes) {
+    _fetch(url, headers, method, input._bodyBytes, fetchId, 'bytes', redirect);
   } else if (typeof input.body === 'object') {
     if (input.body instanceof FormData) {
       const { contentType, body } = generateMultipartFormData(input.body);
       headers.set('Content-Type', contentType);
-      _fetch(url, headers, method, body, fetchId, 'string');
+      _fetch(url, headers, method, body, fetchId, 'string', redirect);
     } else {
-      _fetch(url, headers, method, null, fetchId, 'stream');
-      for await (const chunk of input.body) {
-        _fetch(null, null, null, chunk, fetchId, 'stream');
+      _fetch(url, headers, method, null, fetchId, 'stream', redirect);
+      try {
+        for await (const chunk of input.body) {
+          if (signal && signal.aborted) {
+            break;
+          }
+          // 0 means the chunk is waiting for room in the request body
+          if (_fetch(null, null, null, toArrayBuffer(chunk), fetchId, 'stream') === 0) {
+            await new Promise(resolve => drainWaiters[fetchId] = resolve);
+          }
+        }
+      } catch (e) {
+        // The request can't be sent without the rest of its body
+        abortFetch(fetchId);
+        delete fetchCbs[fetchId];
+        throw e;
+      }
+      if (!(signal && signal.aborted)) {
+        _fetch(null, null, null, false, fetchId, 'stream');
       }
-      _fetch(null, null, null, false, fetchId, 'stream');
     }
   } else {
-    _fetch(url, headers, method, null, fetchId, 'none');
+    _fetch(url, headers, method, null, fetchId, 'none', redirect);
   }
 
   return p;
@@ -5,9 +5,12 @@ export default class Headers {
     // TODO This really should be private and not exposed to user code. It's
     // exposed for now to more easily pass it into native code. In the future,
     // we can just call `entries()` to get the underlying headers.
-    this._headers = {};
+    // It's a list of [name, value] pairs, in the order they were added, so
+    // that repeated headers such as Set-Cookie survive the trip to and from
+    // native code.
+    this._headers = [];
     if (init instanceof Headers) {
-      for (const [name, value] of Headers.prototype.keys.apply(init)) {
+      for (const [name, value] of init._headers) {
         this.append(name, value);
       }
     } else if (typeof init === 'object') {
@@ -37,40 +40,59 @@ export default class Headers {
   set(name, value) {
     name = normalizeHeaderName(name);
     value = normalizeHeaderValue(value);
-    this._headers[name] = String(value);
+    this._headers = this._headers.filter(([n]) => n !== name);
+    this._headers.push([name, value]);
   }
 
   append(name, value) {
     name = normalizeHeaderName(name);
     value = normalizeHeaderValue(value);
-    if (name in this._headers) {
-      this._headers[name] += ', ' + value;
-    } else {
-      this._headers[name] = value;
-    }
+    this._headers.push([name, value]);
   }
 
   get(name) {
     name = normalizeHeaderName(name);
-    return this._headers[name];
+    const values = valuesOf(this._headers, name);
+    return values.length === 0 ? undefined : values.join(', ');
+  }
+
+  // https://fetch.spec.whatwg.org/#dom-headers-getsetcookie
+  // Set-Cookie values can contain commas, so they can't be combined like the
+  // values of other headers.
+  getSetCookie() {
+    return valuesOf(this._headers, 'set-cookie');
   }
 
   has(name) {
     name = normalizeHeaderName(name);
-    return name in this._headers;
+    return this._headers.some(([n]) => n === name);
   }
 
   delete(name) {
     name = normalizeHeaderName(name);
-    delete this._headers[name];
+    this._headers = this._headers.filter(([n]) => n !== name);
   }
 
+  // https://fetch.spec.whatwg.org/#concept-header-list-sort-and-combine
   *[Symbol.iterator]() {
-    yield* Object.entries(this._headers);
+    const names = [...new Set(this._headers.map(([name]) => name))].sort();
+    for (const name of names) {
+      if (name === 'set-cookie') {
+        for (const value of this.getSetCookie()) {
+          yield [name, value];
+        }
+      } else {
+        yield [name, this.get(name)];
+      }
+    }
   }
 }
 IteratorMixin.mixin(Headers);
 
+function valuesOf(list, name) {
+  return list.filter(([n]) => n === name).map(([, value]) => value);
+}
+
 // https://tools.ietf.org/html/rfc7230#section-3.2.6
 const headerNameRe = /^[\^_`a-zA-Z\-0-9!#$%&'*+.|~]+$/;
 function normalizeHeaderName(name) {
@@ -1,18 +1,53 @@
 import { generateContextObject } from 'internal:context.js';
-import { isBufferish } from 'internal:common.js';
+import { outgoingCookies } from 'internal:cookies.js';
+import { isBufferish, toArrayBuffer } from 'internal:common.js';
 import Headers from 'internal:headers.js';
 import Response from 'internal:response.js';
 import Request from 'internal:request.js';
+import { AbortController } from 'internal:abort.js';
+import EventStream, {
+  eventStreamResponse,
+  watchEventStreamResponse
+} from 'internal:event_stream.js';
 
 const {
   sendError,
   startResponse,
   writeResponse,
+  setResponseDrainHandler,
+  resumeRequestBody,
   stringResponse,
   setFetchHandler,
   setIncomingReqHeadHandler,
+  setRequestAbortHandler,
 } = self._bindings;
 
+// The controllers for the signals of requests which haven't been answered yet
+const requestControllers = new Map();
+
+// How many bytes of a request's body are queued up before delivering more of
+// it is paused, until the worker reads some of them
+const BODY_HIGH_WATER_MARK = 64 * 1024;
+
+// Resolves the writes which are waiting for room in their response's body
+const drainWaiters = new Map();
+function responseDrainHandler(reqId) {
+  const resolve = drainWaiters.get(reqId);
+  if (resolve) {
+    drainWaiters.delete(reqId);
+    resolve();
+  }
+}
+setResponseDrainHandler(responseDrainHandler);
+
+// Resolves once the response body has room for more, if it doesn't already
+function write(chunk, reqId) {
+  if (writeResponse(chunk, reqId)) {
+    return;
+  }
+  return new Promise(resolve => drainWaiters.set(reqId, resolve));
+}
+
 // This function checks to see if the object should serialize into a POJO
 // Object, one that is free of class instances. "Double getters" do exist.
 // For example, it could first reply wth a string, and later reply a class
@@ -59,7 +94,7 @@ function shouldSerializeIntoPOJO(obj) {
   }
 }
 
-function incomingReqHeadHandler(reqId, fn, method, url, headers) {
+function incomingReqHeadHandler(reqId, fn, method, url, headers, params, cookies) {
   let writer;
   (async () => {
     try {
@@ -69,44 +104,87 @@ function incomingReqHeadHandler(reqId, fn, method, url, headers) {
       const body = new ReadableStream({
         start(controller) {
           writer = controller;
+        },
+        pull() {
+          resumeRequestBody(reqId);
         }
+      }, {
+        highWaterMark: BODY_HIGH_WATER_MARK,
+        size: chunk => chunk.byteLength
       });
+      const abortController = new AbortController();
+      requestControllers.set(reqId, abortController);
       const request = new Request(url, {
         method,
         headers,
-        body
+        body,
+        signal: abortController.signal
       });
-      await getResponse(reqId, fn, url, request);
+      await getResponse(reqId, fn, url, params, cookies, request);
     } catch (e) {
       console.error(e.stack);
       sendError(500, '', reqId);
     }
   })();
-  return async function handleIncomingReqBody(body) {
-    if (typeof body === 'undefined') {
-      await writer.close();
-    } else {
-      await writer.enqueue(body);
+  // Returns whether there's room for more of the body. Once there isn't, the
+  // body is paused until the stream pulls for more.
+  return function handleIncomingReqBody(body) {
+    try {
+      if (typeof body === 'undefined') {
+        writer.close();
+        return false;
+      }
+      writer.enqueue(body);
+      return writer.desiredSize > 0;
+    } catch (e) {
+      // The worker cancelled the body
+      return false;
     }
   };
 }
 setIncomingReqHeadHandler(incomingReqHeadHandler);
 
+// Called once the response has been sent, or the client has disconnected
+function requestAbortHandler(reqId) {
+  const controller = requestControllers.get(reqId);
+  if (controller) {
+    requestControllers.delete(reqId);
+    controller.abort();
+  }
+}
+setRequestAbortHandler(requestAbortHandler);
+
 function isPromise(p) {
   return typeof p === 'object' && p !== null && typeof p.then === 'function';
 }
 
-async function getResponse(reqId, fn, url, request) {
-  let response = fn(request, generateContextObject(url));
+async function getResponse(reqId, fn, url, params, cookies, request) {
+  const context = generateContextObject(url, params, cookies);
+  let response = fn(request, context);
   if (isPromise(response)) {
     response = await response;
   }
 
+  let eventStream = null;
+  if (response instanceof EventStream) {
+    eventStream = response;
+    response = eventStreamResponse(eventStream);
+  }
+
   switch (typeof response) {
     case 'string': {
-      // handle it in native code
-      stringResponse(response, reqId);
-      return;
+      const setCookies = outgoingCookies(context.cookies);
+      if (setCookies.length === 0) {
+        // handle it in native code
+        stringResponse(response, reqId);
+        return;
+      }
+      response = new Response(response, {
+        headers: new Headers({
+          'Content-Type': 'text/plain'
+        })
+      });
+      break;
     }
     case 'object': {
       if (response === null) {
@@ -139,36 +217,24 @@ async function getResponse(reqId, fn, url, request) {
   }
 
   if (response.body) {
-    startResponse(response, reqId);
+    startResponse(response, reqId, outgoingCookies(context.cookies));
+    if (eventStream) {
+      watchEventStreamResponse(reqId, eventStream);
+    }
     let stream =
       response.body instanceof TransformStream
       ? response.body.readable
       : response.body;
     for await (let chunk of stream) {
-      writeResponse(chunkAsArrayBuffer(chunk), reqId);
+      await write(toArrayBuffer(chunk), reqId);
     }
     writeResponse(null, reqId);
   } else {
-    startResponse(response, reqId, response._bodyString);
-  }
-}
-
-function chunkAsArrayBuffer(chunk) {
-  if (!(chunk instanceof ArrayBuffer)) {
-    if (typeof chunk === 'string') {
-      const enc = new TextEncoder();
-      chunk = enc.encode(chunk).buffer;
-      return chunk;
-    }
-    if (typeof chunk === 'object') {
-      if (chunk.buffer && chunk.buffer instanceof ArrayBuffer) {
-        chunk = chunk.buffer;
-      } else {
-        throw new TypeError(
-          'body chunks must be strings, ArrayBuffers, TypedArrays or DataViews'
-        );
-      }
-    }
+    startResponse(
+      response,
+      reqId,
+      outgoingCookies(context.cookies),
+      response._bodyString
+    );
   }
-  return chunk;
 }
@@ -1,3 +1,4 @@
+import Blob from 'internal:blob.js';
 import FormData from 'internal:form_data.js';
 import Headers from 'internal:headers.js';
 import Response from 'internal:response.js';
@@ -5,16 +6,23 @@ import Request from 'internal:request.js';
 import { atob, btoa } from 'internal:base64.js';
 import { setInterval, setTimeout, clearTimeout } from 'internal:timers.js';
 import fetch from 'internal:fetch.js';
+import EventStream from 'internal:event_stream.js';
+import { AbortController, AbortSignal } from 'internal:abort.js';
 import 'internal:inbound.js';
+import 'internal:websocket.js';
 import 'internal:console.js';
 
 delete self._bindings;
 
 Object.assign(self, {
+  Blob,
   FormData,
   Headers,
   Response,
   Request,
+  EventStream,
+  AbortController,
+  AbortSignal,
   atob,
   btoa,
   setInterval,
@@ -1,7 +1,9 @@
 import BodyMixin from 'internal:body_mixin.js';
-import { StringReadable, isBufferish } from 'internal:common.js';
+import { StringReadable, isBufferish, toArrayBuffer } from 'internal:common.js';
+import Blob, { blobBytes } from 'internal:blob.js';
 import Headers from 'internal:headers.js';
 import FormData from 'internal:form_data.js';
+import { AbortController } from 'internal:abort.js';
 
 const { getPrivate } = _bindings;
 
@@ -10,7 +12,10 @@ const headersSym = getPrivate('headers');
 const urlSym = getPrivate('url');
 const methodSym = getPrivate('method');
 const bodySym = getPrivate('body');
+const signalSym = getPrivate('signal');
+const redirectSym = getPrivate('redirect');
 const _bodyStringSym = getPrivate('_bodyString');
+const _bodyBytesSym = getPrivate('_bodyBytes');
 
 export default class Request {
   // #rawHeaders; // not yet instantiated
@@ -18,7 +23,10 @@ export default class Request {
   // #url;
   // #method;
   // #body;
+  // #signal;
+  // #redirect;
   // #_bodyString;
+  // #_bodyBytes;
   constructor(input, init = {}) {
     // TODO support `input` being a Request
     this[urlSym] = input;
@@ -29,13 +37,26 @@ export default class Request {
       this[headersSym] = init.headers;
     }
     this[methodSym] = init.method || 'GET';
+    this[signalSym] = init.signal || new AbortController().signal;
+    const redirect = init.redirect || 'follow';
+    if (!['follow', 'manual', 'error'].includes(redirect)) {
+      throw new TypeError(`redirect must be 'follow', 'manual' or 'error', got '${redirect}'`);
+    }
+    this[redirectSym] = redirect;
 
     if (init.body instanceof ReadableStream || init.body instanceof FormData) {
       this[bodySym] = init.body;
     } else if (typeof init.body === 'string') {
       this[_bodyStringSym] = init.body;
-    } else if (isBufferish(init.body)) {
-      this[bodySym] = new StringReadable(init.body);
+    } else if (isBufferish(init.body) || init.body instanceof Blob) {
+      // Binary bodies are copied, so that later changes to them aren't sent
+      const bytes = init.body instanceof Blob ?
+        blobBytes(init.body) : toArrayBuffer(init.body).slice(0);
+      this[_bodyBytesSym] = bytes;
+      this[bodySym] = new StringReadable(bytes);
+      if (init.body.type && !this.headers.has('content-type')) {
+        this.headers.set('Content-Type', init.body.type);
+      }
     }
   }
 
@@ -58,8 +79,20 @@ export default class Request {
     return this[bodySym];
   }
 
+  get signal() {
+    return this[signalSym];
+  }
+
+  get redirect() {
+    return this[redirectSym];
+  }
+
   get _bodyString() {
     return this[_bodyStringSym];
   }
+
+  get _bodyBytes() {
+    return this[_bodyBytesSym];
+  }
 }
 BodyMixin.mixin(Request);
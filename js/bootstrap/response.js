@@ -9,6 +9,8 @@ const headersSym = getPrivate('headers');
 const statusSym = getPrivate('status');
 const statusTextSym = getPrivate('statusText');
 const bodySym = getPrivate('body');
+const urlSym = getPrivate('url');
+const redirectedSym = getPrivate('redirected');
 const _bodyStringSym = getPrivate('_bodyString');
 
 export default class Response {
@@ -17,6 +19,8 @@ export default class Response {
   // #status;
   // #statusText;
   // #body;
+  // #url; // only set for responses to fetches
+  // #redirected;
   // #_bodyString;
   constructor(body, init = {}) {
     this[statusSym] = init.status || 200;
@@ -54,9 +58,25 @@ export default class Response {
     return this[bodySym];
   }
 
+  get url() {
+    return this[urlSym] || '';
+  }
+
+  get redirected() {
+    return Boolean(this[redirectedSym]);
+  }
+
   get _bodyString() {
     return this[_bodyStringSym];
   }
 
 }
 BodyMixin.mixin(Response);
+
+// Creates the response to a fetch, which also knows the URL it came from
+export function fetchResponse(body, meta) {
+  const response = new Response(body, meta);
+  response[urlSym] = meta.url;
+  response[redirectedSym] = meta.redirects > 0;
+  return response;
+}
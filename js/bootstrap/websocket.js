@@ -0,0 +1,159 @@
+import { generateContextObject } from 'internal:context.js';
+import { closeCookies } from 'internal:cookies.js';
+import { isBufferish } from 'internal:common.js';
+import Request from 'internal:request.js';
+
+const {
+  setWebSocketHandlers,
+  webSocketSend,
+  webSocketClose,
+  getPrivate,
+} = self._bindings;
+
+const idSym = getPrivate('id');
+const queueSym = getPrivate('queue');
+const wakeSym = getPrivate('wake');
+
+const OPEN = 1;
+const CLOSING = 2;
+const CLOSED = 3;
+
+const sockets = new Map();
+
+// The server's end of a WebSocket connection. Messages can either be handled
+// by assigning `onmessage`, or by iterating over the socket with `for await`.
+class ServerWebSocket {
+  constructor(id, request) {
+    this[idSym] = id;
+    this[queueSym] = [];
+    this[wakeSym] = null;
+    this.request = request;
+    this.url = request.url;
+    this.readyState = OPEN;
+    this.onmessage = null;
+    this.onclose = null;
+  }
+
+  send(data) {
+    if (this.readyState !== OPEN) {
+      throw new Error('WebSocket is not open');
+    }
+    if (typeof data !== 'string') {
+      if (!isBufferish(data)) {
+        throw new TypeError(
+          'data must be a string, ArrayBuffer, TypedArray or DataView'
+        );
+      }
+      if (!(data instanceof ArrayBuffer)) {
+        data = data.buffer.slice(data.byteOffset, data.byteOffset + data.byteLength);
+      }
+    }
+    webSocketSend(this[idSym], data);
+  }
+
+  close(code = 1000, reason = '') {
+    if (code !== 1000 && (code < 3000 || code > 4999 || !Number.isInteger(code))) {
+      throw new RangeError('close code must be 1000, or between 3000 and 4999');
+    }
+    closeSocket(this, code, reason);
+  }
+
+  async *[Symbol.asyncIterator]() {
+    while (true) {
+      if (this[queueSym].length > 0) {
+        yield this[queueSym].shift();
+      } else if (this.readyState === CLOSED) {
+        return;
+      } else {
+        await new Promise(resolve => {
+          this[wakeSym] = resolve;
+        });
+      }
+    }
+  }
+}
+
+function closeSocket(socket, code, reason) {
+  if (socket.readyState !== OPEN) {
+    return;
+  }
+  socket.readyState = CLOSING;
+  webSocketClose(socket[idSym], code, String(reason));
+}
+
+function receive(socket, data) {
+  if (typeof socket.onmessage === 'function') {
+    try {
+      socket.onmessage({ data });
+    } catch (e) {
+      console.error(e.stack);
+    }
+  } else {
+    socket[queueSym].push(data);
+  }
+  wake(socket);
+}
+
+function closed(socket, code, reason) {
+  socket.readyState = CLOSED;
+  if (typeof socket.onclose === 'function') {
+    try {
+      socket.onclose({ code, reason });
+    } catch (e) {
+      console.error(e.stack);
+    }
+  }
+  wake(socket);
+}
+
+function wake(socket) {
+  const resolve = socket[wakeSym];
+  socket[wakeSym] = null;
+  if (resolve) {
+    resolve();
+  }
+}
+
+// Returns whether the connection was accepted, i.e. whether the handler ran
+// without throwing.
+function openWebSocket(socketId, fn, url, headers, params, cookies) {
+  try {
+    if (typeof fn !== 'function') {
+      throw new TypeError('Worker did not provide a valid handler');
+    }
+    const request = new Request(url, { method: 'GET', headers });
+    const socket = new ServerWebSocket(socketId, request);
+    sockets.set(socketId, socket);
+    const context = generateContextObject(url, params, cookies);
+    closeCookies(context.cookies, 'Cookies can\'t be set on WebSocket connections');
+    const result = fn(socket, context);
+    if (result && typeof result.then === 'function') {
+      result.then(null, e => {
+        console.error(e.stack);
+        closeSocket(socket, 1011, 'internal error');
+      });
+    }
+    return true;
+  } catch (e) {
+    console.error(e.stack);
+    sockets.delete(socketId);
+    return false;
+  }
+}
+
+// Called with the data of each message, and then with a null message, a code
+// and a reason once the connection is closed.
+function handleWebSocketEvent(socketId, data, code, reason) {
+  const socket = sockets.get(socketId);
+  if (!socket) {
+    return;
+  }
+  if (data === null) {
+    sockets.delete(socketId);
+    closed(socket, code, reason);
+  } else {
+    receive(socket, data);
+  }
+}
+
+setWebSocketHandlers(openWebSocket, handleWebSocketEvent);
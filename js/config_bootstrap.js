@@ -81,60 +81,307 @@
   // host defaults to localhost
   app.host = 'localhost';
 
-  app.routes = [];
-  app.staticRoutes = [];
+  // shutdownTimeout defaults to 10 seconds
+  let shutdownTimeout = 10000;
+  Reflect.defineProperty(app, 'shutdownTimeout', {
+    get: () => shutdownTimeout,
+    set(t) {
+      if (!Number.isInteger(t) || t < 0) {
+        throw new Error('shutdownTimeout must be a non-negative number of milliseconds');
+      }
+      shutdownTimeout = t;
+    },
+    enumerable: true,
+    configurable: false
+  });
+
+  // tls defaults to undefined, meaning plain HTTP
+  let tls;
+  Reflect.defineProperty(app, 'tls', {
+    get: () => tls,
+    set(t) {
+      if (t === null || t === undefined) {
+        tls = undefined;
+        return;
+      }
+      if (
+        typeof t !== 'object' ||
+        typeof t.cert !== 'string' ||
+        typeof t.key !== 'string'
+      ) {
+        throw new Error('tls must be an object with cert and key file paths');
+      }
+      tls = { cert: t.cert, key: t.key };
+    },
+    enumerable: true,
+    configurable: false
+  });
+
+  // cookieSecret is used to sign cookies set with `{ signed: true }`, and to
+  // check the signatures of signed cookies. It defaults to undefined, meaning
+  // signed cookies can't be used.
+  let cookieSecret;
+  Reflect.defineProperty(app, 'cookieSecret', {
+    get: () => cookieSecret,
+    set(secret) {
+      if (secret === null || secret === undefined) {
+        cookieSecret = undefined;
+        return;
+      }
+      if (typeof secret !== 'string' || secret.length < 32) {
+        throw new Error('cookieSecret must be a string of at least 32 characters');
+      }
+      cookieSecret = secret;
+    },
+    enumerable: true,
+    configurable: false
+  });
+
+  // statsPort defaults to undefined, meaning the app's statistics aren't
+  // served. They're only served on the loopback interface.
+  let statsPort;
+  Reflect.defineProperty(app, 'statsPort', {
+    get: () => statsPort,
+    set(p) {
+      if (p === null || p === undefined) {
+        statsPort = undefined;
+        return;
+      }
+      if (!Number.isInteger(p) || p < 1 || p > 65535) {
+        throw new Error('statsPort must be a number between 1 and 65535');
+      }
+      statsPort = p;
+    },
+    enumerable: true,
+    configurable: false
+  });
+
+  // outboundPool configures the connection pools shared by outbound fetches.
+  // Any settings which aren't provided keep their defaults.
+  const outboundPoolDefaults = {
+    maxIdlePerHost: 32,
+    idleTimeout: 90000,
+    dnsThreads: 4,
+    statsInterval: 0
+  };
+  let outboundPool = { ...outboundPoolDefaults };
+  Reflect.defineProperty(app, 'outboundPool', {
+    get: () => outboundPool,
+    set(p) {
+      if (typeof p !== 'object' || p === null) {
+        throw new Error('outboundPool must be an object');
+      }
+      const pool = { ...outboundPoolDefaults, ...p };
+      if (!Number.isInteger(pool.maxIdlePerHost) || pool.maxIdlePerHost < 0) {
+        throw new Error('maxIdlePerHost must be a non-negative integer');
+      }
+      if (!Number.isInteger(pool.idleTimeout) || pool.idleTimeout < 0) {
+        throw new Error('idleTimeout must be a non-negative number of milliseconds');
+      }
+      if (!Number.isInteger(pool.dnsThreads) || pool.dnsThreads < 1) {
+        throw new Error('dnsThreads must be a positive integer');
+      }
+      if (!Number.isInteger(pool.statsInterval) || pool.statsInterval < 0) {
+        throw new Error('statsInterval must be a non-negative number of milliseconds');
+      }
+      outboundPool = pool;
+    },
+    enumerable: true,
+    configurable: false
+  });
+
+  // Outbound HTTP policy patterns are URLs without a query string or fragment,
+  // whose host and path may contain globs, and whose port may be `*`.
+  const checkPolicyPattern = pattern => {
+    if (typeof pattern !== 'string') {
+      throw new TypeError('policy pattern must be a string');
+    }
+    const match = /^(https?):\/\/([^/?#]+)(\/[^?#]*)?$/.exec(pattern);
+    if (!match) {
+      throw new TypeError(
+        `policy pattern must be an http or https URL without a query string or fragment: ${pattern}`
+      );
+    }
+    const port = /:([^\]:]*)$/.exec(match[2]);
+    if (port && port[1] !== '*' &&
+        !(/^\d+$/.test(port[1]) && Number(port[1]) <= 65535)) {
+      throw new TypeError(`policy pattern has an invalid port: ${pattern}`);
+    }
+  };
 
-  app.static = (routePrefix, directory, options = {}) => {
-    if (typeof routePrefix !== 'string') {
-      throw new TypeError('routePrefix must be a string');
+  // deniedAddresses are the IP address ranges which outbound fetches may not
+  // connect to. By default, these are the loopback, private and link-local
+  // ranges, so that workers can't reach internal services.
+  let deniedAddresses = [
+    '0.0.0.0/8',
+    '10.0.0.0/8',
+    '100.64.0.0/10',
+    '127.0.0.0/8',
+    '169.254.0.0/16',
+    '172.16.0.0/12',
+    '192.168.0.0/16',
+    '::/128',
+    '::1/128',
+    'fc00::/7',
+    'fe80::/10'
+  ];
+  Reflect.defineProperty(app, 'deniedAddresses', {
+    get: () => deniedAddresses,
+    set(d) {
+      if (!Array.isArray(d) || !d.every(range => typeof range === 'string')) {
+        throw new Error('deniedAddresses must be an array of address ranges');
+      }
+      deniedAddresses = [...d];
+    },
+    enumerable: true,
+    configurable: false
+  });
+
+  // Fills in defaults for any route options which weren't provided
+  const formatRouteOptions = options => {
+    if (typeof options !== 'object' || options === null) {
+      throw new TypeError('route options must be an object');
     }
 
-    if (routePrefix.endsWith('/')) {
-      routePrefix = routePrefix.substring(0, routePrefix.length - 1);
+    const {
+      instances = 1,
+      timeout = 0,
+      maxHeapMb = 0,
+      policyMode = 'enforce'
+    } = options;
+    if (!Number.isInteger(instances) || instances < 1) {
+      throw new TypeError('instances must be a positive integer');
     }
 
-    if (typeof directory !== 'string') {
-      throw new TypeError('directory must be a string');
+    if (!Number.isInteger(timeout) || timeout < 0) {
+      throw new TypeError('timeout must be a non-negative number of milliseconds');
     }
 
-    if (directory.endsWith('/')) {
-      directory = directory.substring(0, directory.length - 1);
+    if (!Number.isInteger(maxHeapMb) || maxHeapMb < 0) {
+      throw new TypeError('maxHeapMb must be a non-negative integer');
     }
 
-    app.staticRoutes.push({ routePrefix, directory, options });
-  };
+    if (policyMode !== 'enforce' && policyMode !== 'report') {
+      throw new TypeError(`policyMode must be 'enforce' or 'report'`);
+    }
 
-  const formatRoute = route => {
-    const formattedRoute = route.replace(/\:([a-zA-Z0-9_]+)/g, '*');
-    return formattedRoute;
+    return { instances, timeout, maxHeapMb, policyMode };
   };
 
-  // TODO: method should also accept an array
-  app.route = (method, route, worker, policyFn = () => {}) => {
-    const policyWriter = {
-      outboundHttp: {}
+  // Adds the routing methods (`route`, `static`, `get`, etc.) to an origin.
+  // The `app` object itself is the default origin.
+  const addRoutingMethods = origin => {
+    origin.routes = [];
+    origin.staticRoutes = [];
+
+    origin.static = (routePrefix, directory, options = {}) => {
+      if (typeof routePrefix !== 'string') {
+        throw new TypeError('routePrefix must be a string');
+      }
+
+      if (routePrefix.endsWith('/')) {
+        routePrefix = routePrefix.substring(0, routePrefix.length - 1);
+      }
+
+      if (typeof directory !== 'string') {
+        throw new TypeError('directory must be a string');
+      }
+
+      if (directory.endsWith('/')) {
+        directory = directory.substring(0, directory.length - 1);
+      }
+
+      origin.staticRoutes.push({ routePrefix, directory, options });
     };
-    const policies = [];
+
+    const addRoute = (websocket, method, route, worker, policyFn = () => {}, options = {}) => {
+      const methods = Array.isArray(method) ? method : [method];
+      if (
+        methods.length === 0 ||
+        !methods.every(m => typeof m === 'string' && m.length > 0)
+      ) {
+        throw new TypeError('method must be a string or an array of strings');
+      }
+      if (typeof policyFn === 'object' && policyFn !== null) {
+        options = policyFn;
+        policyFn = () => {};
+      }
+      const policyWriter = {
+        outboundHttp: {}
+      };
+      const policies = [];
+      let outboundTimeout = 0;
+      policyWriter.outboundHttp.timeout = ms => {
+        if (!Number.isInteger(ms) || ms < 0) {
+          throw new TypeError('outbound timeout must be a non-negative number of milliseconds');
+        }
+        outboundTimeout = ms;
+      };
+      const addPolicy = (allow, method) => pattern => {
+        checkPolicyPattern(pattern);
+        policies.push({ allow, method, pattern });
+      };
+      for (const method of httpMethods) {
+        policyWriter.outboundHttp[`allow${method}`] = addPolicy(true, method.toUpperCase());
+        policyWriter.outboundHttp[`deny${method}`] = addPolicy(false, method.toUpperCase());
+      }
+      policyWriter.outboundHttp.allowAny = addPolicy(true, '*');
+      policyWriter.outboundHttp.denyAny = addPolicy(false, '*');
+      // Once a header is named by a header policy, it may only be sent to URLs
+      // matching a header policy which names it
+      const headerPolicies = [];
+      policyWriter.outboundHttp.allowHeaders = (pattern, headers) => {
+        checkPolicyPattern(pattern);
+        if (!Array.isArray(headers) ||
+            !headers.every(h => typeof h === 'string' && h.length > 0)) {
+          throw new TypeError('headers must be an array of header names');
+        }
+        headerPolicies.push({ pattern, headers: headers.map(h => h.toLowerCase()) });
+      };
+      policyFn(policyWriter);
+      origin.routes.push({
+        methods: [...new Set(methods.map(m => m.toUpperCase()))],
+        rawPattern: route,
+        file: worker,
+        policies,
+        headerPolicies,
+        outboundTimeout,
+        options: formatRouteOptions(options),
+        websocket
+      });
+    };
+
+    origin.route = (...args) => addRoute(false, ...args);
+
+    // WebSocket connections are opened with a GET request
+    origin.websocket = (...args) => addRoute(true, 'GET', ...args);
+
+    // Syntax Sugar
     for (const method of httpMethods) {
-      policyWriter.outboundHttp[`allow${method}`] = pattern => {
-        // TODO check if pattern is formatted correctly (no hashes or query params)
-        policies.push({ method: method.toUpperCase(), pattern });
+      origin[method.toLowerCase()] = (route, worker, policyFn, options) => {
+        origin.route(method.toUpperCase(), route, worker, policyFn, options);
       };
     }
-    policyFn(policyWriter);
-    app.routes.push({
-      method,
-      pattern: formatRoute(route),
-      rawPattern: route,
-      file: worker,
-      policies
-    });
   };
 
-  // Syntax Sugar
-  for (const method of httpMethods) {
-    app[method.toLowerCase()] = (route, worker, policyFn) => {
-      app.route(method.toUpperCase(), route, worker, policyFn);
-    };
-  }
+  addRoutingMethods(app);
+
+  // Additional origins (virtual hosts). Requests are dispatched to an origin
+  // based on their Host header, falling back to the default origin.
+  app.origins = [];
+
+  app.origin = (host, originFn) => {
+    if (typeof host !== 'string') {
+      throw new TypeError('host must be a string');
+    }
+
+    if (typeof originFn !== 'function') {
+      throw new TypeError('origin must be configured with a function');
+    }
+
+    const origin = { host: host.toLowerCase() };
+    addRoutingMethods(origin);
+    originFn(origin);
+    app.origins.push(origin);
+  };
 }
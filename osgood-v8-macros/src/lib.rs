@@ -16,8 +16,10 @@ pub fn v8_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
     (quote! {
         #vis extern "C" fn #name(args: *const osgood_v8::V8::FunctionCallbackInfo) {
             let args = osgood_v8::wrapper::FunctionCallbackInfo::new(args);
-            handle_scope!({
-                (|#inputs|#block)(args);
+            osgood_v8::wrapper::catch_panic(|| {
+                handle_scope!({
+                    (|#inputs|#block)(args);
+                });
             });
         }
     })
@@ -2,6 +2,12 @@ use super::*;
 
 pub use V8::Array;
 
+impl Array {
+    pub fn new(length: i32) -> Local<V8::Array> {
+        unsafe { V8::Array_New(Isolate::raw(), length).into() }
+    }
+}
+
 impl Local<V8::Array> {
     pub fn length(&mut self) -> i32 {
         unsafe { self.inner_mut().Length() as i32 }
@@ -1,4 +1,4 @@
-use super::{Context, IntoValue, Local, V8};
+use super::{resume_pending_panic, Context, IntoValue, Isolate, Local, Valuable, V8};
 
 use V8::Function;
 
@@ -9,19 +9,23 @@ impl Local<Function> {
         recv: &IntoValue,
         argv: Vec<&IntoValue>,
     ) -> Local<V8::Value> {
+        // When the call throws, or execution is terminated, there's no return value, so `null` is
+        // returned instead. If execution was terminated because a callback panicked, the panic is
+        // resumed here.
         let argc = argv.len() as i32;
         let mut argv: Vec<V8::Local<V8::Value>> =
             argv.iter().map(|&arg| arg.into_value().into()).collect();
-        unsafe {
-            self.inner_mut()
-                .Call(
-                    context.into(),
-                    recv.into_value().into(),
-                    argc,
-                    argv.as_mut_ptr(),
-                )
-                .to_local_checked()
-                .unwrap()
-        }
+        let result = unsafe {
+            self.inner_mut().Call(
+                context.into(),
+                recv.into_value().into(),
+                argc,
+                argv.as_mut_ptr(),
+            )
+        };
+        resume_pending_panic();
+        result
+            .to_local_checked()
+            .unwrap_or_else(|| Isolate::null().as_value())
     }
 }
@@ -14,12 +14,18 @@ impl convert::From<Isolate> for *mut V8::Isolate {
 
 impl Isolate {
     pub fn new() -> Isolate {
+        Isolate::with_max_heap(None)
+    }
+
+    /// Creates an isolate whose old generation heap is limited to the given number of megabytes.
+    /// Without a limit, V8's default is used.
+    pub fn with_max_heap(max_heap_mb: Option<usize>) -> Isolate {
         unsafe {
             let params = V8::Isolate_CreateParams {
                 code_event_handler: None,
                 constraints: V8::ResourceConstraints {
                     max_semi_space_size_in_kb_: 0,
-                    max_old_space_size_: 0,
+                    max_old_space_size_: max_heap_mb.unwrap_or(0),
                     stack_limit_: ptr::null_mut(),
                     code_range_size_: 0,
                     max_zone_pool_size_: 0,
@@ -64,6 +70,44 @@ impl Isolate {
             self.isolate_.as_mut().unwrap().Dispose();
         }
     }
+
+    /// Forcefully terminates the JavaScript currently running in this isolate. This is the only
+    /// method which may be called from another thread, via `thread_safe()`.
+    pub fn terminate_execution(self) {
+        unsafe {
+            self.isolate_.as_mut().unwrap().TerminateExecution();
+        }
+    }
+
+    /// Resumes the ability to run JavaScript after `terminate_execution()`, including clearing a
+    /// termination which was requested while no JavaScript was running.
+    pub fn cancel_terminate_execution(self) {
+        unsafe {
+            self.isolate_.as_mut().unwrap().CancelTerminateExecution();
+        }
+    }
+
+    pub fn is_execution_terminating(self) -> bool {
+        unsafe { self.isolate_.as_mut().unwrap().IsExecutionTerminating() }
+    }
+
+    /// Registers a callback which V8 invokes when the heap is close to its limit. The callback
+    /// returns the new heap limit, which may be raised to give the isolate room to wind down.
+    pub fn add_near_heap_limit_callback(self, callback: V8::NearHeapLimitCallback) {
+        unsafe {
+            self.isolate_
+                .as_mut()
+                .unwrap()
+                .AddNearHeapLimitCallback(callback, ptr::null_mut());
+        }
+    }
+
+    pub fn thread_safe(self) -> ThreadSafeIsolate {
+        ThreadSafeIsolate {
+            isolate_: self.isolate_,
+        }
+    }
+
     pub fn throw_error(error_string: &str) {
         unsafe {
             let error_string = V8::String::new_from_slice(error_string);
@@ -102,3 +146,22 @@ impl Default for Isolate {
         Self::new()
     }
 }
+
+/// A handle to an isolate which can be sent to other threads. It only exposes the operations which
+/// V8 allows to be performed from a thread other than the one the isolate runs on.
+///
+/// The handle doesn't keep the isolate alive, so the owner must make sure it isn't used after the
+/// isolate has been disposed.
+#[derive(Debug, Copy, Clone)]
+pub struct ThreadSafeIsolate {
+    isolate_: *mut V8::Isolate,
+}
+
+unsafe impl Send for ThreadSafeIsolate {}
+unsafe impl Sync for ThreadSafeIsolate {}
+
+impl ThreadSafeIsolate {
+    pub fn terminate_execution(self) {
+        Isolate::from(self.isolate_).terminate_execution();
+    }
+}
@@ -51,6 +51,9 @@ pub use exception::*;
 mod private;
 pub use private::*;
 
+mod panic;
+pub use panic::*;
+
 /// This is a convenience `None`, which can be used by reference as a "null" in arguments to v8
 /// functions.
 pub const NULL: Option<u16> = None;
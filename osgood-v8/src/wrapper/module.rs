@@ -48,6 +48,7 @@ impl Local<Module> {
 
     pub fn evaluate(&mut self, ctx: Local<V8::Context>) -> Result<Local<V8::Value>, String> {
         let result = unsafe { osgood::evaluate_module(Isolate::raw(), ctx.into(), (*self).into()) };
+        resume_pending_panic();
         if result.is_exception {
             Err(Local::from(result.ret_val)
                 .to_object()
@@ -0,0 +1,28 @@
+use super::*;
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+
+thread_local! {
+    static PENDING_PANIC: RefCell<Option<Box<Any + Send>>> = RefCell::new(None);
+}
+
+/// Runs a callback which was called by V8. A panic can't unwind through V8's stack frames, so it's
+/// caught here instead, and the JavaScript which called the callback is terminated. The panic is
+/// resumed by `resume_pending_panic()` once V8 has returned control to Rust.
+pub fn catch_panic<F: FnOnce()>(callback: F) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(callback)) {
+        PENDING_PANIC.with(|pending| {
+            *pending.borrow_mut() = Some(payload);
+        });
+        Isolate::from(Isolate::raw()).terminate_execution();
+    }
+}
+
+/// Resumes a panic which was caught by `catch_panic()`, if there is one.
+pub fn resume_pending_panic() {
+    if let Some(payload) = PENDING_PANIC.with(|pending| pending.borrow_mut().take()) {
+        panic::resume_unwind(payload);
+    }
+}
@@ -1,39 +1,196 @@
 use super::osgood_v8::wrapper::*;
 use super::osgood_v8::V8;
-use glob::Pattern;
-use std::net::SocketAddr;
+use glob::{MatchOptions, Pattern};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use url::Url;
 
 use path_clean::clean;
 use std::path::PathBuf;
 
 static CONFIG_BOOTSTRAP: &str = include_str!("../js/config_bootstrap.js");
 
+/// Host patterns are matched without regard to case.
+static HOST_MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// A URL pattern from an outbound HTTP policy.
+///
+/// The pattern is split into its parts, which are matched separately: the scheme and the port
+/// must be the same, while the host and the path are globs. Patterns without a port only match the
+/// scheme's default port, and `*` matches any port.
+#[derive(Clone)]
+struct UrlPattern {
+    scheme: std::string::String,
+    host: Pattern,
+    /// `None` matches any port.
+    port: Option<u16>,
+    path: Pattern,
+}
+
+impl UrlPattern {
+    fn new(pattern: &str) -> UrlPattern {
+        // The pattern was checked to be `scheme://authority/path` when it was configured
+        let (scheme, rest) = pattern.split_at(pattern.find("://").unwrap());
+        let rest = &rest[3..];
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(index) if !authority[index..].contains(']') => {
+                (&authority[..index], Some(&authority[index + 1..]))
+            }
+            _ => (authority, None),
+        };
+        let port = match port {
+            Some("*") => None,
+            Some(port) => Some(port.parse().unwrap()),
+            None if scheme == "https" => Some(443),
+            None => Some(80),
+        };
+        // IPv6 addresses are in brackets, which would otherwise be read as a character class
+        let host = if host.starts_with('[') {
+            Pattern::escape(host)
+        } else {
+            host.to_owned()
+        };
+        UrlPattern {
+            scheme: scheme.to_lowercase(),
+            host: Pattern::new(&host).unwrap(),
+            port,
+            path: Pattern::new(path).unwrap(),
+        }
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        self.scheme == url.scheme()
+            && self
+                .host
+                .matches_with(url.host_str().unwrap_or(""), HOST_MATCH_OPTIONS)
+            && (self.port.is_none() || self.port == url.port_or_known_default())
+            && self.path.matches(&decode_unreserved(url.path()))
+    }
+}
+
+/// Percent-decodes the characters which never need to be encoded in a path, since servers treat
+/// e.g. `/%61dmin` as `/admin`. Other encoded characters, like `%2F`, are left alone, since
+/// decoding them would change the meaning of the path. The path is ASCII, since the URL parser
+/// percent-encodes everything else.
+fn decode_unreserved(path: &str) -> std::string::String {
+    let bytes = path.as_bytes();
+    let mut decoded = std::string::String::with_capacity(path.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i..i + 3) {
+            Some([b'%', high, low]) if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                u8::from_str_radix(&path[i + 1..i + 3], 16)
+                    .ok()
+                    .map(char::from)
+            }
+            _ => None,
+        };
+        match escaped.filter(|c| c.is_ascii_alphanumeric() || "-._~".contains(*c)) {
+            Some(c) => {
+                decoded.push(c);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i] as char);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// An outbound HTTP policy entry, which either allows or denies requests whose method and URL
+/// match it.
+#[derive(Clone)]
 pub struct Policy {
+    pub allow: bool,
+    /// The method the policy applies to, or `*` for every method.
     method: std::string::String,
-    pattern: Pattern,
+    url: UrlPattern,
 }
 
 impl Policy {
     fn new(mut v8_policy: Local<V8::Object>, context: Local<V8::Context>) -> Policy {
+        let allow = v8_policy.get(context, "allow").as_rust_bool(context);
         let method = v8_policy.get(context, "method").as_rust_string();
         let pattern = v8_policy.get(context, "pattern").as_rust_string();
         Policy {
+            allow,
             method,
-            pattern: Pattern::new(&pattern).unwrap(),
+            url: UrlPattern::new(&pattern),
+        }
+    }
+
+    pub fn matches(&self, method: &str, url: &Url) -> bool {
+        (self.method == "*" || self.method == method) && self.url.matches(url)
+    }
+}
+
+/// An outbound HTTP header policy, which allows the named headers to be sent to URLs matching it.
+/// Headers which no header policy names aren't restricted.
+#[derive(Clone)]
+pub struct HeaderPolicy {
+    url: UrlPattern,
+    /// Lowercase header names.
+    pub headers: Vec<std::string::String>,
+}
+
+impl HeaderPolicy {
+    fn new(mut v8_policy: Local<V8::Object>, context: Local<V8::Context>) -> HeaderPolicy {
+        let pattern = v8_policy.get(context, "pattern").as_rust_string();
+        let headers = v8_policy
+            .get(context, "headers")
+            .to_object()
+            .iter(context)
+            .map(|(_, v8_header)| v8_header.as_rust_string())
+            .collect();
+        HeaderPolicy {
+            url: UrlPattern::new(&pattern),
+            headers,
         }
     }
 
-    pub fn matches(&self, method: &str, url: &str) -> bool {
-        self.method == method && self.pattern.matches(url)
+    pub fn allows(&self, header: &str, url: &Url) -> bool {
+        self.headers.iter().any(|name| name == header) && self.url.matches(url)
     }
 }
 
+/// What happens when an outbound request violates a route's policies.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PolicyMode {
+    /// The request fails.
+    Enforce,
+    /// The request is allowed through, but the violation is still reported. This is for trying
+    /// out policies on existing applications.
+    Report,
+}
+
+#[derive(Clone)]
 pub struct ConfigRoute {
-    pub method: std::string::String,
-    pub pattern: Pattern,
+    pub methods: Vec<std::string::String>,
     pub worker_file: std::string::String,
     pub policies: Vec<Policy>,
+    pub header_policies: Vec<HeaderPolicy>,
+    pub policy_mode: PolicyMode,
+    /// How long outbound fetches may take, including their response bodies.
+    pub outbound_timeout: Option<Duration>,
     pub raw: std::string::String,
+    pub instances: usize,
+    pub timeout: Option<Duration>,
+    pub max_heap_mb: Option<usize>,
+    /// Whether the route serves WebSocket connections, rather than plain requests.
+    pub websocket: bool,
+    /// The app's secret for signing cookies, if it has one.
+    pub cookie_secret: Option<std::string::String>,
 }
 
 impl ConfigRoute {
@@ -42,10 +199,15 @@ impl ConfigRoute {
         context: Local<V8::Context>,
         worker_base: &PathBuf,
     ) -> ConfigRoute {
-        let method = v8_route.get(context, "method").as_rust_string();
-        let pattern = v8_route.get(context, "pattern").as_rust_string();
+        let methods = v8_route
+            .get(context, "methods")
+            .to_object()
+            .iter(context)
+            .map(|(_, v8_method)| v8_method.as_rust_string())
+            .collect();
         let raw_pattern = v8_route.get(context, "rawPattern").as_rust_string();
         let worker_file = v8_route.get(context, "file").as_rust_string();
+        let websocket = v8_route.get(context, "websocket").as_rust_bool(context);
         let worker_file = get_absolute(worker_base, &PathBuf::from(worker_file))
             .to_string_lossy()
             .into();
@@ -54,13 +216,49 @@ impl ConfigRoute {
         for (_, v8_policy) in v8_route.get(context, "policies").to_object().iter(context) {
             policies.push(Policy::new(v8_policy.to_object(), context));
         }
+        let mut header_policies = Vec::new();
+        for (_, v8_policy) in v8_route
+            .get(context, "headerPolicies")
+            .to_object()
+            .iter(context)
+        {
+            header_policies.push(HeaderPolicy::new(v8_policy.to_object(), context));
+        }
+
+        let outbound_timeout =
+            match v8_route.get(context, "outboundTimeout").to_number().value() as u64 {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            };
+
+        let mut options = v8_route.get(context, "options").to_object();
+        let instances = options.get(context, "instances").to_number().value() as usize;
+        let timeout = match options.get(context, "timeout").to_number().value() as u64 {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+        let max_heap_mb = match options.get(context, "maxHeapMb").to_number().value() as usize {
+            0 => None,
+            mb => Some(mb),
+        };
+        let policy_mode = match options.get(context, "policyMode").as_rust_string().as_str() {
+            "report" => PolicyMode::Report,
+            _ => PolicyMode::Enforce,
+        };
 
         ConfigRoute {
-            method,
-            pattern: Pattern::new(&pattern).unwrap(),
+            methods,
             worker_file,
             policies,
+            header_policies,
+            policy_mode,
+            outbound_timeout,
             raw: raw_pattern,
+            instances,
+            timeout,
+            max_heap_mb,
+            websocket,
+            cookie_secret: None,
         }
     }
 }
@@ -119,10 +317,152 @@ impl ConfigStaticRoute {
     }
 }
 
-// This struct may seem out of place or unnecessary, but it's part of a future plan to support
-// multiple hostnames (think vhosts) in a single app.
+#[derive(Clone)]
+pub struct ConfigTls {
+    pub cert: std::string::String,
+    pub key: std::string::String,
+}
+
+impl ConfigTls {
+    fn new(mut v8_tls: Local<V8::Object>, context: Local<Context>, base: &PathBuf) -> ConfigTls {
+        let cert = v8_tls.get(context, "cert").as_rust_string();
+        let key = v8_tls.get(context, "key").as_rust_string();
+        ConfigTls {
+            cert: get_absolute(base, &PathBuf::from(cert))
+                .to_string_lossy()
+                .into(),
+            key: get_absolute(base, &PathBuf::from(key))
+                .to_string_lossy()
+                .into(),
+        }
+    }
+
+    pub fn scheme(tls: &Option<ConfigTls>) -> &'static str {
+        match tls {
+            Some(_) => "https",
+            None => "http",
+        }
+    }
+
+    pub fn default_port(tls: &Option<ConfigTls>) -> u16 {
+        match tls {
+            Some(_) => 443,
+            None => 80,
+        }
+    }
+}
+
+/// A range of IP addresses in CIDR notation, e.g. `10.0.0.0/8`. A single address is a range
+/// containing just that address.
+#[derive(Clone, PartialEq)]
+pub struct AddressRange {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl AddressRange {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        // IPv4 addresses mapped into IPv6 are checked as IPv4 addresses
+        let ip = match ip {
+            IpAddr::V6(v6) if v6.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => {
+                let octets = v6.octets();
+                IpAddr::from([octets[12], octets[13], octets[14], octets[15]])
+            }
+            _ => *ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::max_value()
+                    .checked_shl(32 - self.prefix_len)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::max_value()
+                    .checked_shl(128 - self.prefix_len)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for AddressRange {
+    type Err = std::string::String;
+
+    fn from_str(range: &str) -> Result<AddressRange, std::string::String> {
+        let invalid = || format!("Invalid address range: {}", range);
+        let (address, prefix_len) = match range.find('/') {
+            Some(index) => (&range[..index], Some(&range[index + 1..])),
+            None => (range, None),
+        };
+        let network: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(invalid)?,
+            None => max_prefix_len,
+        };
+        Ok(AddressRange {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// Settings for the connection pools which outbound fetches share, one per scheme.
+#[derive(Clone, PartialEq)]
+pub struct ConfigOutboundPool {
+    pub max_idle_per_host: usize,
+    /// How long idle connections are kept open. `None` keeps them open indefinitely.
+    pub idle_timeout: Option<Duration>,
+    /// The number of threads each pool uses for resolving hostnames.
+    pub dns_threads: usize,
+    /// How often the pools' statistics are logged, if at all.
+    pub stats_interval: Option<Duration>,
+    /// Outbound fetches fail when their host is, or resolves to, an address in these ranges.
+    pub denied_addresses: Vec<AddressRange>,
+}
+
+impl ConfigOutboundPool {
+    /// Reads `app.outboundPool`, along with `app.deniedAddresses`.
+    fn new(
+        mut v8_config: Local<V8::Object>,
+        context: Local<Context>,
+    ) -> Result<ConfigOutboundPool, std::string::String> {
+        let mut v8_pool = v8_config.get(context, "outboundPool").to_object();
+        let mut millis = |name| match v8_pool.get(context, name).to_number().value() as u64 {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+        let idle_timeout = millis("idleTimeout");
+        let stats_interval = millis("statsInterval");
+        let denied_addresses = v8_config
+            .get(context, "deniedAddresses")
+            .to_object()
+            .iter(context)
+            .map(|(_, v8_range)| v8_range.as_rust_string().parse())
+            .collect::<Result<_, _>>()?;
+        Ok(ConfigOutboundPool {
+            max_idle_per_host: v8_pool.get(context, "maxIdlePerHost").to_number().value() as usize,
+            idle_timeout,
+            dns_threads: v8_pool.get(context, "dnsThreads").to_number().value() as usize,
+            stats_interval,
+            denied_addresses,
+        })
+    }
+}
+
+// An origin is a single hostname (think vhosts) within the app, each with its own routes. The
+// hostname may contain `*` wildcards, e.g. `*.example.com`.
 pub struct ConfigOrigin {
+    pub host: std::string::String,
     pub origin: std::string::String,
+    pub pattern: Pattern,
     pub routes: Vec<ConfigRoute>,
     pub static_routes: Vec<ConfigStaticRoute>,
 }
@@ -132,17 +472,24 @@ impl ConfigOrigin {
         mut v8_origin: Local<V8::Object>,
         context: Local<V8::Context>,
         port: &str,
+        tls: &Option<ConfigTls>,
         worker_base: &PathBuf,
     ) -> ConfigOrigin {
-        // TODO: Need to implement the HTTPS case
-        let host = v8_origin.get(context, "host").as_rust_string();
+        let host = v8_origin
+            .get(context, "host")
+            .as_rust_string()
+            .to_lowercase();
 
-        let mut origin = format!("http://{}", host);
+        let mut origin = format!("{}://{}", ConfigTls::scheme(tls), host);
 
-        if port != "80" {
+        if port != ConfigTls::default_port(tls).to_string() {
             origin = format!("{}:{}", origin, port);
         }
 
+        // Only `*` is treated as special, so that everything else in the origin is matched
+        // literally.
+        let pattern = Pattern::new(&Pattern::escape(&origin).replace("[*]", "*")).unwrap();
+
         let mut routes = Vec::new();
         for (_, v8_route) in v8_origin.get(context, "routes").to_object().iter(context) {
             routes.push(ConfigRoute::new(v8_route.to_object(), context, worker_base));
@@ -162,24 +509,43 @@ impl ConfigOrigin {
         }
 
         ConfigOrigin {
+            host,
             origin,
+            pattern,
             routes,
             static_routes,
         }
     }
+
+    pub fn is_wildcard(&self) -> bool {
+        self.host.contains('*')
+    }
 }
 
 pub struct Config {
-    pub origin: ConfigOrigin, // We'll need a Vec of these eventually.
+    /// The default origin (configured directly on `app`) comes first, followed by any origins
+    /// added with `app.origin()`.
+    pub origins: Vec<ConfigOrigin>,
     pub addr: SocketAddr,
+    pub tls: Option<ConfigTls>,
+    /// How long in-flight requests may take to finish when shutting down.
+    pub shutdown_timeout: Duration,
+    pub outbound_pool: ConfigOutboundPool,
+    /// The loopback port the app's statistics are served on, if any.
+    pub stats_port: Option<u16>,
 }
 
 impl Config {
     pub fn new(filename: &str) -> Result<Config, std::string::String> {
         let file_path = get_absolute(&std::env::current_dir().unwrap(), &PathBuf::from(filename));
-        let config_js = std::fs::read_to_string(&file_path).expect("Bad config file");
+        let config_js = std::fs::read_to_string(&file_path).map_err(|e| format!("{}", e))?;
         let worker_base = PathBuf::from(&file_path.parent().unwrap());
-        let mut origin = None;
+        let mut origins = Vec::new();
+        let mut tls = None;
+        let mut shutdown_timeout = Duration::from_millis(0);
+        let mut outbound_pool = None;
+        let mut cookie_secret = None;
+        let mut stats_port = None;
         let mut addr = Err(std::string::String::from("addr not yet retrieved"));
         v8_simple_init!(|mut context: Local<Context>| {
             let src = v8_str!(CONFIG_BOOTSTRAP);
@@ -204,8 +570,51 @@ impl Config {
                             Err(_) => v8_config.get(context, "port").as_rust_string(),
                         };
                         let interface = v8_config.get(context, "interface").as_rust_string();
+                        shutdown_timeout = Duration::from_millis(
+                            v8_config
+                                .get(context, "shutdownTimeout")
+                                .to_number()
+                                .value() as u64,
+                        );
+
+                        outbound_pool = Some(ConfigOutboundPool::new(v8_config, context));
+
+                        if let TypeofTypes::Number = v8_config.get(context, "statsPort").type_of() {
+                            stats_port = Some(
+                                v8_config.get(context, "statsPort").to_number().value() as u16,
+                            );
+                        }
+
+                        if let TypeofTypes::String =
+                            v8_config.get(context, "cookieSecret").type_of()
+                        {
+                            cookie_secret =
+                                Some(v8_config.get(context, "cookieSecret").as_rust_string());
+                        }
 
-                        origin = Some(ConfigOrigin::new(v8_config, context, &port, &worker_base));
+                        if let TypeofTypes::Object = v8_config.get(context, "tls").type_of() {
+                            let v8_tls = v8_config.get(context, "tls").to_object();
+                            tls = Some(ConfigTls::new(v8_tls, context, &worker_base));
+                        }
+
+                        origins.push(ConfigOrigin::new(
+                            v8_config,
+                            context,
+                            &port,
+                            &tls,
+                            &worker_base,
+                        ));
+                        for (_, v8_origin) in
+                            v8_config.get(context, "origins").to_object().iter(context)
+                        {
+                            origins.push(ConfigOrigin::new(
+                                v8_origin.to_object(),
+                                context,
+                                &port,
+                                &tls,
+                                &worker_base,
+                            ));
+                        }
 
                         match port.parse::<u16>() {
                             Ok(port) => format!("{}:{}", interface, port)
@@ -222,10 +631,27 @@ impl Config {
         });
 
         match addr {
-            Ok(addr) => match origin {
-                Some(origin) => Ok(Config { origin, addr }),
-                None => Err(std::string::String::from("origin was not populated!")),
-            },
+            Ok(addr) => {
+                if origins.is_empty() {
+                    Err(std::string::String::from("origin was not populated!"))
+                } else {
+                    let outbound_pool = outbound_pool.unwrap()?;
+                    // The secret is shared by every route in the app
+                    for origin in &mut origins {
+                        for route in &mut origin.routes {
+                            route.cookie_secret = cookie_secret.clone();
+                        }
+                    }
+                    Ok(Config {
+                        origins,
+                        addr,
+                        tls,
+                        shutdown_timeout,
+                        outbound_pool,
+                        stats_port,
+                    })
+                }
+            }
             Err(err) => Err(err),
         }
     }
@@ -10,20 +10,29 @@ extern crate log;
 extern crate clap;
 
 use futures::sink::Sink;
+use futures::stream::Stream;
 use futures::sync::oneshot;
 use futures::{future, Future};
 
+use glob::Pattern;
+
 use hyper::header::HeaderValue;
 use hyper::service::service_fn;
 use hyper::{Body, Request, Response, Server, StatusCode};
 
 use hyper_staticfile;
 use tokio;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::timer::{Delay, Timeout};
+use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+use tokio_tls::{TlsAcceptor, TlsStream};
 
 use std::cell::RefCell;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::string;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 #[macro_export]
 macro_rules! lazy_thread_local {
@@ -41,9 +50,13 @@ macro_rules! lazy_thread_local {
 }
 
 mod config;
+mod router;
 mod worker;
+// After `worker`, for its logging macros
+mod watch;
 
 use config::*;
+use router::{Match, Router};
 use worker::Worker;
 
 thread_local! {
@@ -52,12 +65,36 @@ thread_local! {
 
 type FutureResponse = Box<Future<Item = Response<Body>, Error = std::io::Error> + Send>;
 type ResponseResult = Result<Response<Body>, string::String>;
+type FutureServer = Box<Future<Item = Shutdown, Error = ()> + Send>;
+
+/// The number of TLS handshakes which may be in progress at the same time.
+static TLS_HANDSHAKE_CONCURRENCY: usize = 128;
+
+/// How long a client gets to complete its TLS handshake, so that clients which never finish theirs
+/// can't hold on to the handshake slots.
+static TLS_HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+
+/// How long to wait before accepting connections again after failing to, e.g. because the process
+/// has run out of file descriptors.
+static ACCEPT_ERROR_DELAY_MS: u64 = 1000;
+
+/// How long workers get to tear down their isolates once the server has stopped.
+static TEARDOWN_GRACE_MS: u64 = 1000;
+
+/// How the server came to a stop.
+enum Shutdown {
+    /// Every request in flight was finished.
+    Drained,
+    /// The shutdown timeout passed before every request in flight was finished.
+    TimedOut,
+}
 
 fn main() {
     let (v8_flags, options) = parse_args();
     osgood_v8::wrapper::platform_init(&v8_flags);
 
-    tokio::run(future::lazy(move || {
+    let mut runtime = tokio::runtime::Runtime::new().expect("Failed to start the runtime");
+    let shutdown = runtime.block_on(future::lazy(move || -> FutureServer {
         pretty_env_logger::init();
 
         let config_file = options.value_of("APPFILE").unwrap();
@@ -69,39 +106,95 @@ fn main() {
         }
         let config = config.unwrap();
         let addr = config.addr;
-        let origin = (&config.origin.origin).clone();
         let port = addr.port();
-        let static_routes = config.origin.static_routes.clone();
-        let workers = Arc::new(make_workers(config).unwrap());
+        let tls = config.tls.clone();
+        let scheme = ConfigTls::scheme(&tls);
+        let default_port = ConfigTls::default_port(&tls);
+        let shutdown_timeout = config.shutdown_timeout;
+        let outbound_pool = config.outbound_pool.clone();
+        worker::configure_outbound_pool(&outbound_pool);
+        let stats_port = config.stats_port;
+        if let Some(stats_port) = stats_port {
+            tokio::spawn(worker::serve_stats(stats_port));
+        }
+        let origins = match make_origins(config) {
+            Ok(origins) => origins,
+            Err(err) => {
+                log_osgood_error!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        let origin_names: Vec<string::String> = origins.iter().map(|o| o.origin.clone()).collect();
+        let origins = Arc::new(RwLock::new(Arc::new(origins)));
+
+        if options.is_present("watch") {
+            tokio::spawn(watch::watch(
+                config_file.to_owned(),
+                addr,
+                outbound_pool,
+                stats_port,
+                origins.clone(),
+            ));
+        }
 
         // Define the HTTP service
         let service = move || {
-            let workers = workers.clone();
-            let static_routes = static_routes.clone();
+            let origins = origins.clone();
 
             service_fn(move |mut req: Request<Body>| -> FutureResponse {
+                // Hold on to the current origins, in case they're replaced while handling this
+                let origins = origins.read().unwrap().clone();
                 let method = req.method().to_string();
-                // TODO: need to change protocol based on http vs https
-                let mut origin = format!(
-                    "http://{}",
-                    req.headers()[hyper::header::HOST].to_str().unwrap()
-                );
-                if port != 443 && port != 80 && !origin.ends_with(format!(":{}", port).as_str()) {
+                let host = match req.headers().get(hyper::header::HOST).map(|h| h.to_str()) {
+                    Some(Ok(host)) => host,
+                    Some(Err(_)) => return bad_request("invalid host\n".to_owned()),
+                    None => return bad_request("missing host\n".to_owned()),
+                };
+                let mut origin = format!("{}://{}", scheme, host);
+                if port != default_port && !origin.ends_with(format!(":{}", port).as_str()) {
                     origin = format!("{}:{}", origin, port);
                 }
+                let origin = origin.to_lowercase();
                 let route = req.uri().to_string();
                 log_info!("{} {} {}", req.method(), origin, route);
-                let mut worker = None;
-                for a_worker in workers.iter() {
-                    if a_worker.matches(&origin, &method, &route) {
-                        worker = Some(a_worker);
-                        break;
-                    }
+                let selected = select_origin(&origins, &origin);
+                let workers = &selected.workers;
+                let matches = if selected.matches(&origin) {
+                    selected.router.lookup(req.uri().path())
+                } else {
+                    Vec::new()
+                };
+                let is_head = method == "HEAD";
+                let mut found = matches.iter().find(|m| workers[m.index].handles(&method));
+                if found.is_none() && is_head {
+                    // HEAD requests are served by the GET worker, with the body dropped
+                    found = matches.iter().find(|m| workers[m.index].handles("GET"));
                 }
-                let worker = match worker {
-                    Some(worker) => worker,
+                let worker = match found {
+                    Some(found) => {
+                        req.extensions_mut().insert(found.params.clone());
+                        &workers[found.index]
+                    }
                     None => {
-                        for static_route in static_routes.iter() {
+                        let allowed = allowed_methods(workers, &matches);
+                        if !allowed.is_empty() {
+                            let (status, body) = if method == "OPTIONS" {
+                                (StatusCode::NO_CONTENT, Body::empty())
+                            } else {
+                                let body = format!(
+                                    "method not allowed: {} {} {}\n",
+                                    method, origin, route
+                                );
+                                (StatusCode::METHOD_NOT_ALLOWED, body.into())
+                            };
+                            let resp = Response::builder()
+                                .status(status)
+                                .header(hyper::header::ALLOW, allowed.join(", "))
+                                .body(body)
+                                .unwrap();
+                            return Box::new(future::ok(resp));
+                        }
+                        for static_route in selected.static_routes.iter() {
                             let route_prefix = &static_route.route_prefix;
                             let directory = &static_route.directory;
                             let default_file = &static_route.index;
@@ -258,15 +351,22 @@ fn main() {
                     }
                 };
 
-                let sender = worker.sender.clone();
+                // Workers see the absolute URL, since a wildcard origin can't tell them the host
+                *req.uri_mut() = match format!("{}{}", origin, route).parse() {
+                    Ok(uri) => uri,
+                    Err(_) => return bad_request(format!("invalid host: {}\n", origin)),
+                };
+
+                let instance = worker.instance();
 
                 // Create a one-shot, reverse channel so that the worker thread can send its response
                 let (tx, rx) = oneshot::channel();
 
                 // Send the request to the service worker thread, await the response, and send that to
                 // the client
-                Box::new(sender.send((req, tx)).then(|_| {
+                Box::new(instance.sender.clone().send((req, tx)).then(|_| {
                     rx.then(move |res: Result<ResponseResult, oneshot::Canceled>| {
+                        drop(instance);
                         if let Ok(res) = res {
                             if let Ok(res) = res {
                                 future::ok(res)
@@ -287,46 +387,264 @@ fn main() {
                             )
                         }
                     })
+                    .map(move |mut res| {
+                        if is_head {
+                            *res.body_mut() = Body::empty();
+                        }
+                        res
+                    })
                 }))
             })
         };
 
         log_osgood_message!(
             "{}",
-            format!("Application has started, listening on {}", origin)
+            format!(
+                "Application has started, listening on {}",
+                origin_names.join(", ")
+            )
         );
 
-        Server::bind(&addr)
-            .serve(service)
-            .map_err(|e| log_osgood_error!("Error: {}", e))
+        let signal = shutdown_signal().shared();
+        let server: Box<Future<Item = (), Error = ()> + Send> = match tls {
+            Some(tls) => match tls_incoming(&addr, &tls) {
+                Ok(incoming) => Box::new(
+                    Server::builder(incoming)
+                        .serve(service)
+                        .with_graceful_shutdown(signal.clone().then(|_| Ok::<(), ()>(())))
+                        .map_err(|e| log_osgood_error!("Error: {}", e)),
+                ),
+                Err(err) => {
+                    log_osgood_error!("Unable to start TLS listener: {}", err);
+                    std::process::exit(1);
+                }
+            },
+            None => Box::new(
+                Server::bind(&addr)
+                    .serve(service)
+                    .with_graceful_shutdown(signal.clone().then(|_| Ok::<(), ()>(())))
+                    .map_err(|e| log_osgood_error!("Error: {}", e)),
+            ),
+        };
+
+        // The server stops accepting connections as soon as a signal arrives, and finishes once the
+        // connections it has are done. That's given until the shutdown timeout.
+        let deadline = signal.then(move |signal| {
+            log_osgood_message!("Received {}, shutting down", *signal.unwrap());
+            let deadline = Instant::now() + shutdown_timeout;
+            worker::set_shutdown_deadline(deadline);
+            Delay::new(deadline).then(|_| Ok(Shutdown::TimedOut))
+        });
+
+        Box::new(
+            server
+                .map(|_| Shutdown::Drained)
+                .select(deadline)
+                .map(|(shutdown, _)| shutdown)
+                .map_err(|_| ()),
+        )
     }));
-    //tokio::run(server);
 
-    osgood_v8::wrapper::platform_dispose();
+    // Dropping the server's tasks drops the last references to the workers, which closes their
+    // channels so that they tear down their isolates.
+    runtime.shutdown_now().wait().unwrap();
+
+    let mut status = match shutdown {
+        Ok(Shutdown::Drained) => 0,
+        Ok(Shutdown::TimedOut) => {
+            log_osgood_error!("Timed out waiting for requests to finish");
+            1
+        }
+        Err(_) => 1,
+    };
+    if worker::wait_for_workers(Instant::now() + Duration::from_millis(TEARDOWN_GRACE_MS)) {
+        osgood_v8::wrapper::platform_dispose();
+    } else {
+        log_osgood_error!("Timed out waiting for workers to shut down");
+        status = 1;
+    }
+    std::process::exit(status);
+}
+
+/// Resolves with the name of the first shutdown signal which is received. If signals can't be
+/// listened for, it never resolves.
+fn shutdown_signal() -> impl Future<Item = &'static str, Error = ()> {
+    let sigint = Signal::new(SIGINT).flatten_stream().map(|_| "SIGINT");
+    let sigterm = Signal::new(SIGTERM).flatten_stream().map(|_| "SIGTERM");
+    sigint
+        .select(sigterm)
+        .into_future()
+        .map(|(signal, _)| signal.unwrap_or("end of signal stream"))
+        .or_else(|(e, _)| {
+            log_osgood_error!("Unable to listen for signals: {}", e);
+            future::empty()
+        })
+}
+
+/// Answers a request which can't be routed, e.g. because its Host header isn't valid.
+fn bad_request(body: string::String) -> FutureResponse {
+    let resp = Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(body.into())
+        .unwrap();
+    Box::new(future::ok(resp))
+}
+
+/// The methods which a route can be requested with, for `Allow` headers. HEAD and OPTIONS are
+/// handled automatically, so they're included as well. This is empty when nothing matches the
+/// route.
+fn allowed_methods(workers: &[Arc<Worker>], matches: &[Match]) -> Vec<string::String> {
+    let mut methods: Vec<string::String> = Vec::new();
+    for found in matches {
+        for method in workers[found.index].methods() {
+            if !methods.contains(method) {
+                methods.push(method.clone());
+            }
+        }
+    }
+    if methods.is_empty() {
+        return methods;
+    }
+    if methods.iter().any(|m| m == "GET") && !methods.iter().any(|m| m == "HEAD") {
+        methods.push("HEAD".to_owned());
+    }
+    if !methods.iter().any(|m| m == "OPTIONS") {
+        methods.push("OPTIONS".to_owned());
+    }
+    methods
+}
+
+/// The workers and static routes which serve a single origin.
+#[derive(Clone)]
+struct Origin {
+    origin: string::String,
+    pattern: Pattern,
+    wildcard: bool,
+    /// Finds the workers for a request, by their index in `workers`.
+    router: Arc<Router>,
+    workers: Vec<Arc<Worker>>,
+    static_routes: Vec<ConfigStaticRoute>,
+}
+
+impl Origin {
+    fn matches(&self, origin: &str) -> bool {
+        self.pattern.matches(origin)
+    }
+}
+
+/// Exact origins take precedence over wildcard ones, otherwise they're considered in the order
+/// they were declared. When nothing matches, the default origin is used, so that its static
+/// routes are still served.
+fn select_origin<'a>(origins: &'a [Origin], origin: &str) -> &'a Origin {
+    origins
+        .iter()
+        .find(|o| !o.wildcard && o.matches(origin))
+        .or_else(|| origins.iter().find(|o| o.wildcard && o.matches(origin)))
+        .unwrap_or(&origins[0])
+}
+
+fn make_origins(config: Config) -> Result<Vec<Origin>, string::String> {
+    let mut origins = Vec::new();
+    for origin in config.origins {
+        let wildcard = origin.is_wildcard();
+        let mut router = Router::new();
+        for (index, route) in origin.routes.iter().enumerate() {
+            router
+                .insert(&route.raw, &route.methods, index)
+                .map_err(|err| format!("{}: {}", origin.origin, err))?;
+        }
+        origins.push(Origin {
+            origin: origin.origin.clone(),
+            pattern: origin.pattern.clone(),
+            wildcard,
+            router: Arc::new(router),
+            static_routes: origin.static_routes.clone(),
+            workers: make_workers(origin)?,
+        });
+    }
+    Ok(origins)
 }
 
-fn make_workers(config: Config) -> Result<Vec<Worker>, std::io::Error> {
+fn make_workers(origin: ConfigOrigin) -> Result<Vec<Arc<Worker>>, string::String> {
     let mut workers = Vec::new();
-    let origin = config.origin;
     for route in origin.routes {
         if let Ok(handler) = std::fs::read_to_string(&route.worker_file) {
-            workers.push(Worker::new(
-                &origin.origin,
-                &route.method,
-                route.pattern,
-                &handler,
-                &route.worker_file,
-                route.policies,
-                &route.raw,
-            ));
+            workers.push(Arc::new(Worker::new(&origin.host, route, &handler)));
         } else {
-            log_osgood_error!("Could not find worker file: {}", &route.worker_file);
-            std::process::exit(1);
+            return Err(format!(
+                "Could not find worker file: {}",
+                &route.worker_file
+            ));
         }
     }
     Ok(workers)
 }
 
+fn tls_incoming(
+    addr: &SocketAddr,
+    tls: &ConfigTls,
+) -> Result<impl Stream<Item = TlsStream<TcpStream>, Error = std::io::Error>, string::String> {
+    let cert = std::fs::read(&tls.cert).map_err(|e| format!("{}: {}", tls.cert, e))?;
+    let key = std::fs::read(&tls.key).map_err(|e| format!("{}: {}", tls.key, e))?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).map_err(|e| format!("{}", e))?;
+    let acceptor = native_tls::TlsAcceptor::new(identity).map_err(|e| format!("{}", e))?;
+    let acceptor = TlsAcceptor::from(acceptor);
+    let listener = TcpListener::bind(addr).map_err(|e| format!("{}", e))?;
+
+    // A failed handshake only affects that one connection, so it's logged and skipped rather than
+    // being allowed to end the stream of incoming connections. So is a failure to accept one.
+    Ok(listener
+        .incoming()
+        .then(
+            |result| -> Box<Future<Item = Option<TcpStream>, Error = std::io::Error> + Send> {
+                match result {
+                    Ok(socket) => Box::new(future::ok(Some(socket))),
+                    Err(ref err) if is_connection_error(err) => Box::new(future::ok(None)),
+                    Err(err) => {
+                        log_osgood_error!("Unable to accept a connection: {}", err);
+                        let retry = Instant::now() + Duration::from_millis(ACCEPT_ERROR_DELAY_MS);
+                        Box::new(Delay::new(retry).then(|_| Ok(None)))
+                    }
+                }
+            },
+        )
+        .filter_map(|socket| socket)
+        .map(move |socket| {
+            let timeout = Duration::from_millis(TLS_HANDSHAKE_TIMEOUT_MS);
+            Timeout::new(acceptor.accept(socket), timeout).then(
+                |result| -> Result<Option<TlsStream<TcpStream>>, std::io::Error> {
+                    match result {
+                        Ok(stream) => Ok(Some(stream)),
+                        Err(ref err) if err.is_elapsed() => {
+                            log_osgood_error!("TLS handshake timed out");
+                            Ok(None)
+                        }
+                        Err(err) => {
+                            match err.into_inner() {
+                                Some(err) => log_osgood_error!("TLS handshake failed: {}", err),
+                                None => log_osgood_error!("TLS handshake timer failed"),
+                            }
+                            Ok(None)
+                        }
+                    }
+                },
+            )
+        })
+        .buffer_unordered(TLS_HANDSHAKE_CONCURRENCY)
+        .filter_map(|stream| stream))
+}
+
+/// Errors which only affect the connection which was being accepted, like hyper's `AddrIncoming`
+/// skips without waiting.
+fn is_connection_error(err: &std::io::Error) -> bool {
+    match err.kind() {
+        std::io::ErrorKind::ConnectionRefused
+        | std::io::ErrorKind::ConnectionAborted
+        | std::io::ErrorKind::ConnectionReset => true,
+        _ => false,
+    }
+}
+
 fn parse_args<'a>() -> (string::String, clap::ArgMatches<'a>) {
     let (v8_flags_vec, mut args): (Vec<string::String>, Vec<string::String>) =
         std::env::args().partition(|arg| arg.starts_with("--v8-"));
@@ -342,6 +660,11 @@ fn parse_args<'a>() -> (string::String, clap::ArgMatches<'a>) {
     }
     let options = clap::App::new("osgood")
         .version(crate_version!())
+        .arg(
+            clap::Arg::with_name("watch")
+                .long("watch")
+                .help("Reloads workers when the Application file or a worker file changes"),
+        )
         .arg(
             clap::Arg::with_name("APPFILE")
                 .required(true)
@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use std::string;
+
+/// The parameters captured from a request's path, in the order they appear in the route pattern.
+/// They're attached to the request's extensions when it's dispatched, so that the worker doesn't
+/// need to match the path again.
+#[derive(Clone, Debug, Default)]
+pub struct Params(pub Vec<(string::String, string::String)>);
+
+/// A route which matched a request path.
+pub struct Match {
+    /// The index of the route, as given to `Router::insert()`.
+    pub index: usize,
+    pub params: Params,
+}
+
+/// Matches request paths against the route patterns of an origin.
+///
+/// Patterns are stored in a tree of path segments, so a lookup only visits the routes which share
+/// a prefix with the path. At each segment, static segments take precedence over segments which
+/// mix text with parameters, which take precedence over `:params`, then `*` and finally `**`.
+/// Segments which mix text with parameters are tried from the most text to the least. Routes which
+/// could never be chosen because other routes handle their paths and methods first are rejected
+/// when they're inserted.
+#[derive(Default)]
+pub struct Router {
+    root: Node,
+    /// The routes inserted so far, for detecting conflicts.
+    inserted: Vec<Inserted>,
+}
+
+struct Inserted {
+    pattern: string::String,
+    methods: Vec<string::String>,
+    segments: Vec<Segment>,
+}
+
+#[derive(Default)]
+struct Node {
+    statics: HashMap<string::String, Node>,
+    mixed: Vec<(string::String, Vec<Part>, Node)>,
+    param: Option<Box<Node>>,
+    wildcard: Option<Box<Node>>,
+    catch_all: Option<Box<Node>>,
+    /// The routes whose pattern ends at this node.
+    routes: Vec<Entry>,
+}
+
+struct Entry {
+    index: usize,
+    /// The name of each captured value, or `None` for the ones captured by `*` and `**`.
+    names: Vec<Option<string::String>>,
+}
+
+#[derive(Clone)]
+enum Segment {
+    Static(string::String),
+    Mixed(Vec<Part>),
+    Param(string::String),
+    Wildcard,
+    CatchAll,
+}
+
+#[derive(Clone)]
+enum Part {
+    Literal(string::String),
+    Param(string::String),
+    Wildcard,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Adds a route pattern, which handles the given methods. Fails if the route conflicts with
+    /// one which was inserted before.
+    pub fn insert(
+        &mut self,
+        pattern: &str,
+        methods: &[string::String],
+        index: usize,
+    ) -> Result<(), string::String> {
+        if !pattern.starts_with('/') {
+            return Err(format!(
+                "Route pattern must start with a slash: {}",
+                pattern
+            ));
+        }
+        let segments: Vec<Segment> = pattern[1..].split('/').map(parse_segment).collect();
+
+        let shape = pattern_shape(&segments);
+        for existing in self.inserted.iter() {
+            let method = match methods.iter().find(|m| existing.methods.contains(m)) {
+                Some(method) => method,
+                None => continue,
+            };
+            if pattern_shape(&existing.segments) == shape {
+                return Err(format!(
+                    "Route {} {} conflicts with {} {}",
+                    method, pattern, method, existing.pattern
+                ));
+            }
+            if shadows(&existing.segments, &segments) {
+                return Err(format!(
+                    "Route {} {} is shadowed by {} {}",
+                    method, pattern, method, existing.pattern
+                ));
+            }
+            if shadows(&segments, &existing.segments) {
+                return Err(format!(
+                    "Route {} {} is shadowed by {} {}",
+                    method, existing.pattern, method, pattern
+                ));
+            }
+        }
+        self.inserted.push(Inserted {
+            pattern: pattern.to_owned(),
+            methods: methods.to_vec(),
+            segments: segments.clone(),
+        });
+
+        let mut names = Vec::new();
+        let mut node = &mut self.root;
+        for segment in segments {
+            node = match segment {
+                Segment::Static(text) => node.statics.entry(text).or_default(),
+                Segment::Mixed(parts) => {
+                    names.extend(parts.iter().filter_map(|part| match part {
+                        Part::Literal(_) => None,
+                        Part::Param(name) => Some(Some(name.clone())),
+                        Part::Wildcard => Some(None),
+                    }));
+                    let key = mixed_shape(&parts);
+                    let position = match node.mixed.iter().position(|(k, _, _)| *k == key) {
+                        Some(position) => position,
+                        None => {
+                            // After the segments which are at least as specific, so that ties
+                            // keep the order the routes were inserted in
+                            let position = node
+                                .mixed
+                                .iter()
+                                .position(|(_, other, _)| specificity(other) < specificity(&parts))
+                                .unwrap_or(node.mixed.len());
+                            node.mixed.insert(position, (key, parts, Node::default()));
+                            position
+                        }
+                    };
+                    &mut node.mixed[position].2
+                }
+                Segment::Param(name) => {
+                    names.push(Some(name));
+                    &mut **node.param.get_or_insert_with(Default::default)
+                }
+                Segment::Wildcard => {
+                    names.push(None);
+                    &mut **node.wildcard.get_or_insert_with(Default::default)
+                }
+                Segment::CatchAll => {
+                    names.push(None);
+                    &mut **node.catch_all.get_or_insert_with(Default::default)
+                }
+            };
+        }
+        node.routes.push(Entry { index, names });
+        Ok(())
+    }
+
+    /// Finds every route which matches the path, most specific first. The path must not include a
+    /// query string.
+    pub fn lookup(&self, path: &str) -> Vec<Match> {
+        let mut matches = Vec::new();
+        if path.starts_with('/') {
+            let segments: Vec<&str> = path[1..].split('/').collect();
+            self.root.collect(&segments, &mut Vec::new(), &mut matches);
+        }
+        matches
+    }
+}
+
+impl Node {
+    fn collect(
+        &self,
+        segments: &[&str],
+        captures: &mut Vec<string::String>,
+        matches: &mut Vec<Match>,
+    ) {
+        let (segment, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => {
+                matches.extend(self.routes.iter().map(|entry| entry.to_match(captures)));
+                return;
+            }
+        };
+
+        if let Some(child) = self.statics.get(*segment) {
+            child.collect(rest, captures, matches);
+        }
+        for (_, parts, child) in self.mixed.iter() {
+            let len = captures.len();
+            if match_parts(parts, segment, captures) {
+                child.collect(rest, captures, matches);
+            }
+            captures.truncate(len);
+        }
+        if !segment.is_empty() {
+            for child in self.param.iter().chain(self.wildcard.iter()) {
+                captures.push((*segment).to_owned());
+                child.collect(rest, captures, matches);
+                captures.pop();
+            }
+        }
+        if let Some(child) = &self.catch_all {
+            // The longest match comes first, like a greedy glob
+            for end in (1..=segments.len()).rev() {
+                captures.push(segments[..end].join("/"));
+                child.collect(&segments[end..], captures, matches);
+                captures.pop();
+            }
+        }
+    }
+}
+
+impl Entry {
+    fn to_match(&self, captures: &[string::String]) -> Match {
+        let params = self
+            .names
+            .iter()
+            .zip(captures)
+            .filter_map(|(name, value)| name.as_ref().map(|name| (name.clone(), value.clone())))
+            .collect();
+        Match {
+            index: self.index,
+            params: Params(params),
+        }
+    }
+}
+
+fn parse_segment(segment: &str) -> Segment {
+    if segment == "**" {
+        return Segment::CatchAll;
+    }
+
+    let mut parts = Vec::new();
+    let mut literal = string::String::new();
+    let mut chars = segment.chars().peekable();
+    while let Some(c) = chars.next() {
+        let is_name_char = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+        if c == '*' {
+            while chars.peek() == Some(&'*') {
+                chars.next();
+            }
+            push_literal(&mut parts, &mut literal);
+            parts.push(Part::Wildcard);
+        } else if c == ':' && chars.peek().map_or(false, is_name_char) {
+            let mut name = string::String::new();
+            while let Some(c) = chars.peek().cloned().filter(is_name_char) {
+                name.push(c);
+                chars.next();
+            }
+            push_literal(&mut parts, &mut literal);
+            parts.push(Part::Param(name));
+        } else {
+            literal.push(c);
+        }
+    }
+    push_literal(&mut parts, &mut literal);
+
+    match parts.as_slice() {
+        [] => Segment::Static(string::String::new()),
+        [Part::Literal(text)] => Segment::Static(text.clone()),
+        [Part::Param(name)] => Segment::Param(name.clone()),
+        [Part::Wildcard] => Segment::Wildcard,
+        _ => Segment::Mixed(parts),
+    }
+}
+
+fn push_literal(parts: &mut Vec<Part>, literal: &mut string::String) {
+    if !literal.is_empty() {
+        parts.push(Part::Literal(std::mem::replace(
+            literal,
+            string::String::new(),
+        )));
+    }
+}
+
+/// Describes which paths a pattern matches, regardless of the names of its parameters. Patterns
+/// with the same shape match exactly the same paths.
+fn pattern_shape(segments: &[Segment]) -> string::String {
+    let segments: Vec<string::String> = segments.iter().map(segment_shape).collect();
+    format!("/{}", segments.join("/"))
+}
+
+fn segment_shape(segment: &Segment) -> string::String {
+    match segment {
+        Segment::Static(text) => text.clone(),
+        // Braced, since a mixed segment like `:a:b` would otherwise have the same shape as `**`
+        Segment::Mixed(parts) => format!("{{{}}}", mixed_shape(parts)),
+        Segment::Param(_) | Segment::Wildcard => "*".to_owned(),
+        Segment::CatchAll => "**".to_owned(),
+    }
+}
+
+/// Whether a route with the `first` pattern is chosen before one with the `second` pattern for
+/// every path the second matches. That's the case when the first ends with a `**` which the second
+/// continues after, since `**` matches as much of the path as it can, so long as the segments
+/// before it are tried no later than the second's.
+fn shadows(first: &[Segment], second: &[Segment]) -> bool {
+    let (last, prefix) = match first.split_last() {
+        Some((Segment::CatchAll, prefix)) => (prefix.len(), prefix),
+        _ => return false,
+    };
+    match second.get(last) {
+        Some(Segment::CatchAll) if second.len() > last + 1 => {}
+        _ => return false,
+    }
+    prefix.iter().zip(second).all(|(a, b)| match (a, b) {
+        // `:params` are tried before `*`
+        (Segment::Param(_), Segment::Wildcard) => true,
+        (Segment::Wildcard, Segment::Param(_)) => false,
+        (a, b) => segment_shape(a) == segment_shape(b),
+    })
+}
+
+fn mixed_shape(parts: &[Part]) -> string::String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Literal(text) => text.as_str(),
+            Part::Param(_) | Part::Wildcard => "*",
+        })
+        .collect()
+}
+
+/// How much text a segment which mixes text with parameters has, and then how many parameters.
+/// Segments with more of both match fewer paths.
+fn specificity(parts: &[Part]) -> (usize, usize) {
+    parts
+        .iter()
+        .fold((0, 0), |(text, params), part| match part {
+            Part::Literal(literal) => (text + literal.len(), params),
+            Part::Param(_) | Part::Wildcard => (text, params + 1),
+        })
+}
+
+/// Matches a segment which mixes text with parameters, capturing the parameters' values. Each
+/// parameter captures at least one character, and as many as possible.
+fn match_parts(parts: &[Part], text: &str, captures: &mut Vec<string::String>) -> bool {
+    match parts.split_first() {
+        None => text.is_empty(),
+        Some((Part::Literal(literal), rest)) => {
+            text.starts_with(literal.as_str())
+                && match_parts(rest, &text[literal.len()..], captures)
+        }
+        Some((_, rest)) => {
+            for end in (1..=text.len())
+                .rev()
+                .filter(|end| text.is_char_boundary(*end))
+            {
+                captures.push(text[..end].to_owned());
+                if match_parts(rest, &text[end..], captures) {
+                    return true;
+                }
+                captures.pop();
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router(routes: &[&str]) -> Router {
+        let mut router = Router::new();
+        for (index, route) in routes.iter().enumerate() {
+            router.insert(route, &methods(&["GET"]), index).unwrap();
+        }
+        router
+    }
+
+    fn methods(methods: &[&str]) -> Vec<string::String> {
+        methods.iter().map(|m| m.to_string()).collect()
+    }
+
+    /// The index of each matching route, most specific first.
+    fn indexes(router: &Router, path: &str) -> Vec<usize> {
+        router.lookup(path).iter().map(|m| m.index).collect()
+    }
+
+    fn params(router: &Router, path: &str) -> Vec<(string::String, string::String)> {
+        router.lookup(path).remove(0).params.0
+    }
+
+    fn param(name: &str, value: &str) -> (string::String, string::String) {
+        (name.to_owned(), value.to_owned())
+    }
+
+    fn conflict(first: &str, second: &str) -> string::String {
+        let mut router = Router::new();
+        router.insert(first, &methods(&["GET"]), 0).unwrap();
+        router
+            .insert(second, &methods(&["GET"]), 1)
+            .err()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn static_routes() {
+        let router = router(&["/", "/users", "/users/admin"]);
+        assert_eq!(indexes(&router, "/"), vec![0]);
+        assert_eq!(indexes(&router, "/users"), vec![1]);
+        assert_eq!(indexes(&router, "/users/admin"), vec![2]);
+        assert!(indexes(&router, "/users/").is_empty());
+        assert!(indexes(&router, "/posts").is_empty());
+        assert!(indexes(&router, "users").is_empty());
+    }
+
+    #[test]
+    fn params_are_captured() {
+        let router = router(&["/users/:id", "/users/:id/posts/:post_id"]);
+        assert_eq!(params(&router, "/users/42"), vec![param("id", "42")]);
+        assert_eq!(
+            params(&router, "/users/42/posts/7"),
+            vec![param("id", "42"), param("post_id", "7")]
+        );
+        // A parameter needs at least one character
+        assert!(indexes(&router, "/users/").is_empty());
+    }
+
+    #[test]
+    fn params_mixed_with_text() {
+        let router = router(&["/files/:name.json", "/files/:name-:size.png"]);
+        assert_eq!(
+            params(&router, "/files/report.json"),
+            vec![param("name", "report")]
+        );
+        assert_eq!(
+            params(&router, "/files/a-b-100.png"),
+            vec![param("name", "a-b"), param("size", "100")]
+        );
+        assert!(indexes(&router, "/files/.json").is_empty());
+        assert!(indexes(&router, "/files/report.png").is_empty());
+    }
+
+    #[test]
+    fn wildcards_are_not_captured() {
+        let router = router(&["/assets/*/:file", "/docs/**"]);
+        assert_eq!(
+            params(&router, "/assets/v1/app.js"),
+            vec![param("file", "app.js")]
+        );
+        assert!(params(&router, "/docs/guide/intro").is_empty());
+        assert_eq!(indexes(&router, "/docs/guide/intro"), vec![1]);
+        assert!(indexes(&router, "/docs").is_empty());
+    }
+
+    #[test]
+    fn catch_all_matches_as_much_as_it_can() {
+        let router = router(&["/files/**/:name"]);
+        assert_eq!(params(&router, "/files/a/b/c"), vec![param("name", "c")]);
+        assert!(indexes(&router, "/files/c").is_empty());
+    }
+
+    #[test]
+    fn most_specific_route_comes_first() {
+        // Inserted from least to most specific
+        let router = router(&[
+            "/files/**",
+            "/files/:name",
+            "/files/:name.json",
+            "/files/latest",
+        ]);
+        assert_eq!(indexes(&router, "/files/latest"), vec![3, 1, 0]);
+        assert_eq!(indexes(&router, "/files/report.json"), vec![2, 1, 0]);
+        assert_eq!(indexes(&router, "/files/report"), vec![1, 0]);
+        assert_eq!(indexes(&router, "/files/a/b"), vec![0]);
+    }
+
+    #[test]
+    fn mixed_segments_with_more_text_come_first() {
+        let router = router(&["/images/*.*", "/images/:name.png", "/images/:a:b.*"]);
+        assert_eq!(indexes(&router, "/images/logo.png"), vec![1, 2, 0]);
+        assert_eq!(indexes(&router, "/images/logo.gif"), vec![2, 0]);
+        assert_eq!(indexes(&router, "/images/x.gif"), vec![0]);
+    }
+
+    #[test]
+    fn earlier_segments_decide_precedence() {
+        let by_static = router(&["/:section/latest", "/users/:id"]);
+        assert_eq!(indexes(&by_static, "/users/latest"), vec![1, 0]);
+        let by_param = router(&["/*/latest", "/:section/:id"]);
+        assert_eq!(indexes(&by_param, "/users/latest"), vec![1, 0]);
+    }
+
+    #[test]
+    fn identical_shapes_conflict() {
+        assert_eq!(
+            conflict("/users/:id", "/users/:name"),
+            "Route GET /users/:name conflicts with GET /users/:id"
+        );
+        assert_eq!(
+            conflict("/users/:id", "/users/*"),
+            "Route GET /users/* conflicts with GET /users/:id"
+        );
+        assert_eq!(
+            conflict("/files/:name.json", "/files/*.json"),
+            "Route GET /files/*.json conflicts with GET /files/:name.json"
+        );
+        assert_eq!(
+            conflict("/users/:id", "/users/:id"),
+            "Route GET /users/:id conflicts with GET /users/:id"
+        );
+    }
+
+    #[test]
+    fn routes_after_a_catch_all_are_shadowed() {
+        assert_eq!(
+            conflict("/files/**", "/files/**/raw"),
+            "Route GET /files/**/raw is shadowed by GET /files/**"
+        );
+        // Regardless of the order they're inserted in
+        assert_eq!(
+            conflict("/files/**/raw", "/files/**"),
+            "Route GET /files/**/raw is shadowed by GET /files/**"
+        );
+        assert_eq!(
+            conflict("/:user/**", "/*/**/raw"),
+            "Route GET /*/**/raw is shadowed by GET /:user/**"
+        );
+    }
+
+    #[test]
+    fn overlapping_routes_which_are_reachable_dont_conflict() {
+        for (first, second) in &[
+            ("/users/:id", "/users/admin"),
+            ("/users/:id", "/users/:id.json"),
+            ("/users/:id", "/users/**"),
+            ("/files/:a:b", "/files/**"),
+            ("/files/*.*", "/files/*.png"),
+            ("/files/*/**", "/files/**"),
+            // `/files/:name/**/raw` is tried first for paths they both match
+            ("/files/*/**", "/files/:name/**/raw"),
+            ("/files/**/raw", "/files/**/:name"),
+        ] {
+            assert_eq!(conflict(first, second), "", "{} and {}", first, second);
+        }
+    }
+
+    #[test]
+    fn different_methods_dont_conflict() {
+        let mut router = Router::new();
+        router.insert("/users/:id", &methods(&["GET"]), 0).unwrap();
+        router
+            .insert("/users/:name", &methods(&["POST"]), 1)
+            .unwrap();
+        router.insert("/users/**", &methods(&["GET"]), 2).unwrap();
+        router
+            .insert("/users/**/raw", &methods(&["PUT"]), 3)
+            .unwrap();
+        assert_eq!(
+            router.insert("/users/:user", &methods(&["PUT", "POST"]), 4),
+            Err("Route POST /users/:user conflicts with POST /users/:name".to_owned())
+        );
+    }
+
+    #[test]
+    fn patterns_must_start_with_a_slash() {
+        assert_eq!(
+            Router::new().insert("users", &methods(&["GET"]), 0),
+            Err("Route pattern must start with a slash: users".to_owned())
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use super::*;
+
+use tokio::timer::Interval;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// How often the watched files are checked for changes.
+static WATCH_INTERVAL_MS: u64 = 500;
+
+/// Checks the application file and the files loaded by each worker for changes, for use during
+/// development.
+///
+/// When a worker file, or a module imported by one, changes, fresh workers are built for just the
+/// routes which depend on it. When the application file changes, all workers are rebuilt from the
+/// new configuration. Requests which are already in flight are finished by the old workers.
+pub fn watch(
+    config_file: string::String,
+    addr: SocketAddr,
+    outbound_pool: ConfigOutboundPool,
+    stats_port: Option<u16>,
+    origins: Arc<RwLock<Arc<Vec<Origin>>>>,
+) -> impl Future<Item = (), Error = ()> {
+    let config_path = std::env::current_dir().unwrap().join(&config_file);
+    let mut modified_times = HashMap::new();
+    record_changes(&mut modified_times, &watched_files(&config_path, &origins));
+
+    Interval::new_interval(Duration::from_millis(WATCH_INTERVAL_MS))
+        .map_err(|e| log_osgood_error!("File watcher failed: {}", e))
+        .for_each(move |_| {
+            let changed =
+                record_changes(&mut modified_times, &watched_files(&config_path, &origins));
+            if changed.is_empty() {
+                return Ok(());
+            }
+
+            if changed.contains(&config_path) {
+                log_osgood_message!("{} changed, reloading all workers", config_file);
+                reload_config(&config_file, addr, &outbound_pool, stats_port, &origins);
+            } else {
+                reload_workers(&changed, &origins);
+            }
+            // Pick up the files of the new workers, so their changes aren't missed
+            record_changes(&mut modified_times, &watched_files(&config_path, &origins));
+            Ok(())
+        })
+}
+
+fn watched_files(config_path: &PathBuf, origins: &RwLock<Arc<Vec<Origin>>>) -> Vec<PathBuf> {
+    let mut files = vec![config_path.clone()];
+    for origin in origins.read().unwrap().iter() {
+        for worker in origin.workers.iter() {
+            files.extend(worker.files());
+        }
+    }
+    files
+}
+
+/// Records the current modification time of each file, and returns the files whose modification
+/// time differs from the one recorded before. Files seen for the first time don't count as changed.
+fn record_changes(
+    modified_times: &mut HashMap<PathBuf, Option<SystemTime>>,
+    files: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut changed = Vec::new();
+    for file in files {
+        let modified = std::fs::metadata(file).and_then(|m| m.modified()).ok();
+        if let Some(previous) = modified_times.insert(file.clone(), modified) {
+            if previous != modified && !changed.contains(file) {
+                changed.push(file.clone());
+            }
+        }
+    }
+    changed
+}
+
+fn reload_config(
+    config_file: &str,
+    addr: SocketAddr,
+    outbound_pool: &ConfigOutboundPool,
+    stats_port: Option<u16>,
+    origins: &RwLock<Arc<Vec<Origin>>>,
+) {
+    let config = match Config::new(config_file) {
+        Ok(config) => config,
+        Err(err) => {
+            log_osgood_error!("{} failed to load due to:", config_file);
+            log_osgood_error!("{}", err);
+            return;
+        }
+    };
+    if config.addr != addr {
+        log_osgood_error!("Changing the port or interface requires a restart");
+    }
+    if config.outbound_pool != *outbound_pool {
+        log_osgood_error!("Changing the outbound pool or denied addresses requires a restart");
+    }
+    if config.stats_port != stats_port {
+        log_osgood_error!("Changing the stats port requires a restart");
+    }
+    match make_origins(config) {
+        Ok(new_origins) => {
+            *origins.write().unwrap() = Arc::new(new_origins);
+        }
+        Err(err) => log_osgood_error!("{}", err),
+    }
+}
+
+fn reload_workers(changed: &[PathBuf], origins: &RwLock<Arc<Vec<Origin>>>) {
+    let mut new_origins: Vec<Origin> = origins.read().unwrap().iter().cloned().collect();
+    for origin in new_origins.iter_mut() {
+        for worker in origin.workers.iter_mut() {
+            let files = worker.files();
+            if !changed.iter().any(|file| files.contains(file)) {
+                continue;
+            }
+            log_osgood_message!("Reloading {}", worker.name);
+            match worker.reload() {
+                Ok(new_worker) => *worker = Arc::new(new_worker),
+                Err(err) => log_osgood_error!("Unable to reload {}: {}", worker.name, err),
+            }
+        }
+    }
+    *origins.write().unwrap() = Arc::new(new_origins);
+}
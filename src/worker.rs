@@ -1,3 +1,4 @@
+use futures::future::Either;
 use futures::stream::Stream;
 use futures::sync::{mpsc, oneshot};
 use futures::{future, Future};
@@ -8,11 +9,9 @@ use hyper::{
     Body, Request, Response, StatusCode,
 };
 
-use glob::Pattern;
-
 use path_clean::clean;
 
-use super::config::Policy;
+use super::config::{ConfigOutboundPool, ConfigRoute, HeaderPolicy, Policy, PolicyMode};
 use super::osgood_v8::wrapper::*;
 use super::osgood_v8::V8;
 use super::ResponseResult;
@@ -20,177 +19,377 @@ use super::ResponseResult;
 use tokio::runtime::current_thread;
 
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ffi::c_void;
 use std::path::PathBuf;
 use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
 
 use tokio;
 
 #[macro_use]
 pub mod logging;
 
+mod cookies;
 mod fetch;
 mod headers;
 mod inbound;
 mod internal;
 mod policies;
+mod pool;
+mod stats;
+mod supervisor;
 mod timers;
+mod watchdog;
+mod websocket;
 
 /// The size of the MPSC channel buffer (in addition to the number of channel senders).
 static BUFFER_SIZE: usize = 128;
 
+/// The number of worker threads which haven't finished yet.
+static RUNNING_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// Once the server is shutting down, workers only wait this long for requests in flight.
+    static ref SHUTDOWN_DEADLINE: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
 type ResponseResultSender = oneshot::Sender<ResponseResult>;
 type Message = (Request<Body>, ResponseResultSender);
 
 static PREAMBLE: &str = include_str!("../js/dist/preamble.js");
 
+lazy_thread_local!(ISOLATE, set_isolate, Isolate);
 lazy_thread_local!(CONTEXT, set_context, Local<Context>);
 lazy_thread_local!(FETCH_TX, set_fetch_tx, mpsc::Sender<Message>);
 lazy_thread_local!(MODULE_MAP, set_module_map, HashMap<i32, std::string::String>);
+lazy_thread_local!(OUT_OF_MEMORY_TX, set_out_of_memory_tx, oneshot::Sender<()>);
+lazy_thread_local!(WORKER_FILES, set_worker_files, Arc<Mutex<HashSet<PathBuf>>>);
 
 thread_local! {
     static MODULE_CACHE: RefCell<HashMap<PathBuf, Persistent<V8::Module>>> = RefCell::new(HashMap::new());
 }
 
-/// A single instance of a worker.
+/// A route's worker, backed by one or more isolates which all run the same worker file.
 pub struct Worker {
+    pub name: std::string::String,
+    instances: Vec<Instance>,
+    next_instance: AtomicUsize,
+    host: std::string::String,
+    route: ConfigRoute,
+    /// The worker file, along with every module it has imported so far.
+    files: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+/// A single isolate (and its thread) belonging to a worker.
+struct Instance {
+    sender: mpsc::Sender<Message>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// The instance a request has been dispatched to. The instance is considered busy with the
+/// request until this is dropped.
+pub struct InstanceHandle {
     pub sender: mpsc::Sender<Message>,
-    origin: std::string::String,
-    pattern: Pattern,
-    method: std::string::String,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InstanceHandle {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl Worker {
     /// Create a new worker with the given handler script.
-    pub fn new(
-        origin: &str,
-        method: &str,
-        pattern: Pattern,
+    pub fn new(host: &str, route: ConfigRoute, handler: &str) -> Worker {
+        let name = format!("{} {}{}", route.methods.join(","), host, route.raw);
+        let mut files = HashSet::new();
+        files.insert(PathBuf::from(&route.worker_file));
+        let files = Arc::new(Mutex::new(files));
+        let instances = (0..route.instances)
+            .map(|i| {
+                let name = if route.instances > 1 {
+                    format!("{} #{}", name, i + 1)
+                } else {
+                    name.clone()
+                };
+                Instance::new(name, handler, &route, &files)
+            })
+            .collect();
+
+        Worker {
+            name,
+            instances,
+            next_instance: AtomicUsize::new(0),
+            host: host.to_owned(),
+            route,
+            files,
+        }
+    }
+
+    /// Creates a fresh worker for the same route, from the current contents of the worker file.
+    pub fn reload(&self) -> Result<Worker, std::io::Error> {
+        let handler = std::fs::read_to_string(&self.route.worker_file)?;
+        Ok(Worker::new(&self.host, self.route.clone(), &handler))
+    }
+
+    /// The files which the worker's code was loaded from.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn handles(&self, method: &str) -> bool {
+        self.route.methods.iter().any(|m| m == method)
+    }
+
+    pub fn methods(&self) -> &[std::string::String] {
+        &self.route.methods
+    }
+
+    /// Picks the instance with the fewest requests in flight, with ties broken round-robin.
+    pub fn instance(&self) -> InstanceHandle {
+        let len = self.instances.len();
+        let start = self.next_instance.fetch_add(1, Ordering::SeqCst) % len;
+        let instance = (0..len)
+            .map(|i| &self.instances[(start + i) % len])
+            .min_by_key(|instance| instance.in_flight.load(Ordering::SeqCst))
+            .unwrap();
+        instance.in_flight.fetch_add(1, Ordering::SeqCst);
+        InstanceHandle {
+            sender: instance.sender.clone(),
+            in_flight: instance.in_flight.clone(),
+        }
+    }
+}
+
+/// How an isolate's run came to an end.
+enum IsolateExit {
+    /// The worker's channel was closed, so the worker is done.
+    Closed,
+    /// The worker module threw while it was being evaluated.
+    StartFailed,
+    /// The isolate ran out of memory, so it needs to be replaced.
+    OutOfMemory,
+}
+
+impl Instance {
+    fn new(
+        name: std::string::String,
         handler: &str,
-        handler_filename: &str,
-        policies: Vec<Policy>,
-        route: &str,
-    ) -> Worker {
-        // TODO: Once we add support for multiple origins, we should add origin to the name
-        let name = format!("{} {}", method, pattern);
+        config_route: &ConfigRoute,
+        files: &Arc<Mutex<HashSet<PathBuf>>>,
+    ) -> Instance {
         let (inbound_tx, inbound_rx) = mpsc::channel(BUFFER_SIZE); // for inbounds
         let (outbound_tx, outbound_rx) = mpsc::channel(BUFFER_SIZE); // for outbounds
         let handler = handler.to_owned();
-        let name_str = name.to_string();
-        let origin_str = origin.to_owned();
-        let handler_filename = handler_filename.to_owned();
-        let route = route.to_owned();
+        let config_route = config_route.clone();
+        let files = files.clone();
 
-        // Each Isolate runs in a separate thread; communication occurs via the MPSC channel.
+        // Each Isolate runs in a separate thread; communication occurs via the MPSC channel. When
+        // an isolate dies, a fresh one takes over the channel on the same thread.
+        RUNNING_THREADS.fetch_add(1, Ordering::SeqCst);
         std::thread::spawn(move || {
             super::NAME.with(|n| {
-                *n.borrow_mut() = name_str;
+                *n.borrow_mut() = name;
             });
-            policies::set_policies(policies);
-            let task = future::lazy(move || -> Box<Future<Item = (), Error = ()>> {
-                let isolate = Isolate::new();
-                isolate.enter();
-                set_module_map(HashMap::new());
-
-                let scope = HandleScope::new();
-                let mut context = Context::new();
-                context.enter();
-                set_context(context);
-                set_fetch_tx(outbound_tx);
-
-                make_globals(context, &route);
-                run_module(context, PREAMBLE, "preamble.js", None)
-                    .expect("Preamble failed to execute");
-                if let Err(err) = internal::run_internal_module(context, "internal:index.js") {
-                    panic!("Bootstrap failed to execute: {}", err);
-                }
-                match run_module(
-                    context,
-                    &handler,
-                    &handler_filename,
-                    Some(&handler_filename),
-                ) {
-                    Err(errstr) => {
-                        log_worker_error!("Worker failed to start due to thrown error:");
-                        log_worker_error!("{}", errstr);
-                        teardown(isolate, &mut context, scope);
-                        Box::new(future::ok(()))
-                    }
-                    Ok(module) => {
-                        log_info!("Worker started");
-
-                        let mut exports = module.get_exports(context).unwrap();
-
-                        let default_export = exports.get(context, "default");
-                        if default_export.is_function() {
-                            let mut global = context.global();
-                            global.set_private(
-                                context,
-                                "worker_handler",
-                                default_export.to_function(),
-                            );
-                        } else {
-                            log_worker_warning!("Worker did not export a default handler");
-                        }
-
-                        Box::new(
-                            inbound_rx
-                                .for_each(move |message: Message| {
-                                    log_trace!("Inbound fetch");
-                                    current_thread::spawn(inbound::handle_inbound(
-                                        message,
-                                        &origin_str,
-                                    ));
-                                    future::ok(())
-                                })
-                                .then(move |_| {
-                                    log_trace!("Tearing down worker");
-                                    teardown(isolate, &mut context, scope);
-                                    future::ok(())
-                                }),
-                        )
-                    }
-                }
-            });
-            current_thread::run(task);
+            policies::set_policies(
+                config_route.policies.clone(),
+                config_route.header_policies.clone(),
+                config_route.policy_mode,
+            );
+            fetch::set_outbound_timeout(config_route.outbound_timeout);
+            cookies::set_secret(config_route.cookie_secret.clone());
+            set_worker_files(files);
+            supervisor::supervise(inbound_rx, outbound_tx, &handler, &config_route);
+            RUNNING_THREADS.fetch_sub(1, Ordering::SeqCst);
         });
 
         tokio::spawn(future::lazy(move || {
             outbound_rx
                 .for_each(move |(req, tx): Message| {
                     log_trace!("Outbound fetch");
-                    if req.uri().scheme_str().unwrap() == "https" {
-                        hyper::rt::spawn(fetch::fetch_https_outbound(req, tx));
-                    } else {
-                        hyper::rt::spawn(fetch::fetch_http_outbound(req, tx));
-                    }
+                    hyper::rt::spawn(fetch::fetch_outbound(req, tx));
                     future::ok(())
                 })
                 .map_err(|e| error!("{:?}", e))
         }));
 
-        Worker {
+        Instance {
             sender: inbound_tx,
-            origin: origin.to_string(),
-            pattern,
-            method: method.to_string(),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+/// Creates an isolate, starts the worker in it and then serves requests from the channel, until
+/// the channel is closed or the isolate runs out of memory.
+fn run_isolate<'a>(
+    inbound_rx: &'a mut mpsc::Receiver<Message>,
+    outbound_tx: mpsc::Sender<Message>,
+    handler: &'a str,
+    config_route: &'a ConfigRoute,
+) -> Box<Future<Item = IsolateExit, Error = ()> + 'a> {
+    Box::new(future::lazy(
+        move || -> Box<Future<Item = IsolateExit, Error = ()> + 'a> {
+            let handler_filename = &config_route.worker_file;
+            let isolate = Isolate::with_max_heap(config_route.max_heap_mb);
+            isolate.enter();
+            set_isolate(isolate);
+            let (out_of_memory_tx, out_of_memory_rx) = oneshot::channel();
+            set_out_of_memory_tx(out_of_memory_tx);
+            isolate.add_near_heap_limit_callback(Some(near_heap_limit));
+            set_module_map(HashMap::new());
+
+            let scope = HandleScope::new();
+            let mut context = Context::new();
+            context.enter();
+            set_context(context);
+            set_fetch_tx(outbound_tx);
+
+            make_globals(context);
+            run_module(context, PREAMBLE, "preamble.js", None).expect("Preamble failed to execute");
+            if let Err(err) = internal::run_internal_module(context, "internal:index.js") {
+                panic!("Bootstrap failed to execute: {}", err);
+            }
+            match run_module(context, handler, handler_filename, Some(handler_filename)) {
+                Err(errstr) => {
+                    log_worker_error!("Worker failed to start due to thrown error:");
+                    log_worker_error!("{}", errstr);
+                    teardown(isolate, &mut context, scope);
+                    Box::new(future::ok(IsolateExit::StartFailed))
+                }
+                Ok(module) => {
+                    log_info!("Worker started");
+
+                    let mut exports = module.get_exports(context).unwrap();
+
+                    let default_export = exports.get(context, "default");
+                    if default_export.is_function() {
+                        let mut global = context.global();
+                        global.set_private(context, "worker_handler", default_export.to_function());
+                    } else {
+                        log_worker_warning!("Worker did not export a default handler");
+                    }
+
+                    if let Some(timeout) = config_route.timeout {
+                        watchdog::start(isolate, timeout);
+                    }
+
+                    let is_websocket = config_route.websocket;
+                    Box::new(
+                        inbound_rx
+                            .for_each(move |message: Message| {
+                                log_trace!("Inbound fetch");
+                                if is_websocket {
+                                    current_thread::spawn(websocket::handle_inbound(message));
+                                } else {
+                                    current_thread::spawn(inbound::handle_inbound(message));
+                                }
+                                future::ok(())
+                            })
+                            .select2(out_of_memory_rx)
+                            .then(|result| -> Box<Future<Item = IsolateExit, Error = ()>> {
+                                match result {
+                                    Ok(Either::B(_)) => {
+                                        Box::new(future::ok(IsolateExit::OutOfMemory))
+                                    }
+                                    // Once the channel is closed (e.g. because the worker was
+                                    // replaced), the requests which are still in flight are
+                                    // finished before tearing down.
+                                    _ => Box::new(inbound::drained().map(|_| IsolateExit::Closed)),
+                                }
+                            })
+                            .then(move |exit| {
+                                log_trace!("Tearing down worker");
+                                teardown(isolate, &mut context, scope);
+                                exit
+                            }),
+                    )
+                }
+            }
+        },
+    ))
+}
+
+/// Called by V8 when the isolate's heap is nearly full. Rather than letting V8 abort the whole
+/// process, the JavaScript is terminated and the isolate is replaced.
+extern "C" fn near_heap_limit(
+    _data: *mut c_void,
+    current_heap_limit: usize,
+    _initial_heap_limit: usize,
+) -> usize {
+    OUT_OF_MEMORY_TX.with(|tx| {
+        if let Some(tx) = tx.borrow_mut().take() {
+            log_worker_error!("Worker ran out of memory; terminating it");
+            Isolate::from(Isolate::raw()).terminate_execution();
+            let _ = tx.send(());
         }
+    });
+    // Give the terminated JavaScript some room to unwind in.
+    current_heap_limit * 2
+}
+
+/// Clears the state which belonged to a disposed isolate, so that a new one can start afresh.
+fn reset_thread_state() {
+    inbound::abort_requests();
+    websocket::close_sockets();
+    fetch::abort_fetches();
+    timers::clear_timers();
+    internal::clear_module_cache();
+    MODULE_CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+/// Sets up the connection pools which all workers share for their outbound fetches. This must be
+/// done before any workers are started.
+pub fn configure_outbound_pool(config: &ConfigOutboundPool) {
+    pool::configure(config);
+}
+
+/// Serves the statistics of the outbound pools and routes, such as how often each route's
+/// workers have been restarted, on the given loopback port.
+pub fn serve_stats(port: u16) -> impl Future<Item = (), Error = ()> {
+    stats::serve(port)
+}
+
+/// Starts the clock on shutting down. Workers whose channel gets closed wait for their requests in
+/// flight until the deadline, and then tear down their isolates regardless.
+pub fn set_shutdown_deadline(deadline: Instant) {
+    *SHUTDOWN_DEADLINE.lock().unwrap() = Some(deadline);
+}
+
+fn shutdown_deadline_passed() -> bool {
+    match *SHUTDOWN_DEADLINE.lock().unwrap() {
+        Some(deadline) => Instant::now() >= deadline,
+        None => false,
     }
+}
 
-    pub fn matches(&self, origin: &str, method: &str, route: &str) -> bool {
-        let formatted_route = match &route.find('?') {
-            Some(idx) => &route[0..*idx],
-            None => route,
-        };
-        self.origin == origin && self.method == method && self.pattern.matches(&formatted_route)
+/// Waits for every worker thread to finish, which they do once their channel is closed and their
+/// isolate is torn down. Returns `false` if they haven't all finished by the given time.
+pub fn wait_for_workers(until: Instant) -> bool {
+    while RUNNING_THREADS.load(Ordering::SeqCst) > 0 {
+        if Instant::now() >= until {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
     }
+    true
 }
 
 fn teardown(isolate: Isolate, context: &mut Local<Context>, scope: HandleScope) {
+    watchdog::stop();
     context.exit();
     CONTEXT.with(|c| {
         *c.borrow_mut() = None;
     });
+    ISOLATE.with(|i| {
+        *i.borrow_mut() = None;
+    });
     isolate_and_scope_teardown(isolate, scope);
 }
 
@@ -216,29 +415,60 @@ fn get_module_map() -> HashMap<i32, std::string::String> {
     })
 }
 
-fn make_globals(mut context: Local<Context>, route: &str) {
+fn make_globals(mut context: Local<Context>) {
     let mut global = context.global();
     let mut obj = Object::new();
 
     global.set("self", global);
-    obj.set("_route", route);
     obj.set_extern_method(context, "sendError", inbound::send_error);
     obj.set_extern_method(context, "startResponse", inbound::start_response);
     obj.set_extern_method(context, "writeResponse", inbound::write_response);
+    obj.set_extern_method(
+        context,
+        "setResponseDrainHandler",
+        inbound::set_response_drain_handler,
+    );
+    obj.set_extern_method(context, "resumeRequestBody", inbound::resume_request_body);
     obj.set_extern_method(context, "stringResponse", inbound::string_response);
     obj.set_extern_method(context, "setFetchHandler", fetch::set_fetch_handler);
+    obj.set_extern_method(
+        context,
+        "setFetchDrainHandler",
+        fetch::set_fetch_drain_handler,
+    );
     obj.set_extern_method(context, "setTimerHandler", timers::set_timer_handler);
     obj.set_extern_method(
         context,
         "setIncomingReqHeadHandler",
         inbound::set_inbound_req_head_handler,
     );
+    obj.set_extern_method(
+        context,
+        "setWebSocketHandlers",
+        websocket::set_websocket_handlers,
+    );
+    obj.set_extern_method(context, "webSocketSend", websocket::websocket_send);
+    obj.set_extern_method(context, "webSocketClose", websocket::websocket_close);
+    obj.set_extern_method(
+        context,
+        "setRequestAbortHandler",
+        inbound::set_request_abort_handler,
+    );
+    obj.set_extern_method(
+        context,
+        "setEventStreamHandler",
+        inbound::set_event_stream_handler,
+    );
+    obj.set_extern_method(context, "watchEventStream", inbound::watch_event_stream);
     obj.set_extern_method(context, "setTimeout", timers::set_timeout);
     obj.set_extern_method(context, "setInterval", timers::set_interval);
     obj.set_extern_method(context, "clearTimer", timers::clear_timer);
     obj.set_extern_method(context, "_log", log);
     obj.set_extern_method(context, "_error", error);
     obj.set_extern_method(context, "_fetch", fetch::start_fetch);
+    obj.set_extern_method(context, "abortFetch", fetch::abort_fetch);
+    obj.set_extern_method(context, "signCookie", cookies::sign_cookie);
+    obj.set_extern_method(context, "verifyCookie", cookies::verify_cookie);
     if let Ok(_var) = std::env::var("DEBUG") {
         obj.set_extern_method(context, "debug", debug);
     }
@@ -298,6 +528,11 @@ extern "C" fn resolve_module(
         let full_path_str = full_path.to_str().unwrap();
         match std::fs::read_to_string(&full_path) {
             Ok(module) => {
+                WORKER_FILES.with(|files| {
+                    if let Some(files) = files.borrow().as_ref() {
+                        files.lock().unwrap().insert(full_path.clone());
+                    }
+                });
                 let maybe_module = run_module(
                     Local::from(context),
                     module.as_str(),
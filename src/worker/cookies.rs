@@ -0,0 +1,104 @@
+use super::*;
+use hyper::header::COOKIE;
+use url::percent_encoding::percent_decode;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type CookieMac = Hmac<Sha256>;
+
+thread_local! {
+    /// The app's `cookieSecret`, which signed cookies are signed with.
+    static SECRET: RefCell<Option<std::string::String>> = RefCell::new(None);
+}
+
+pub fn set_secret(secret: Option<std::string::String>) {
+    SECRET.with(|s| {
+        *s.borrow_mut() = secret;
+    });
+}
+
+/// Parses the request's `Cookie` headers into an object of names and values. When a name appears
+/// more than once, the first value is used, since browsers send the cookie with the most specific
+/// path first. Values are percent-decoded, undoing the encoding done by `cookies.set()`.
+pub fn v8_cookies(header_map: &HeaderMap) -> Local<V8::Object> {
+    let mut v8_cookies = V8::Object::new();
+    let mut seen = HashSet::new();
+    for header in header_map.get_all(COOKIE) {
+        let header = std::string::String::from_utf8_lossy(header.as_bytes());
+        for pair in header.split(';') {
+            let mut parts = pair.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => continue,
+            };
+            if name.is_empty() || !seen.insert(name.to_owned()) {
+                continue;
+            }
+            let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                &value[1..value.len() - 1]
+            } else {
+                value
+            };
+            let value = percent_decode(value.as_bytes()).decode_utf8_lossy();
+            v8_cookies.set(name, value.as_ref());
+        }
+    }
+    v8_cookies
+}
+
+/// Signs a cookie's value, returning the value followed by a `.` and its signature. The name is
+/// signed along with the value, so that a signed value can't be reused for another cookie. Returns
+/// `undefined` if the app has no secret.
+#[v8_fn]
+pub fn sign_cookie(args: FunctionCallbackInfo) {
+    let name = args.get(0).unwrap().as_rust_string();
+    let value = args.get(1).unwrap().as_rust_string();
+    if let Some(signature) = signature(&name, &value) {
+        let signed = format!("{}.{}", value, signature);
+        args.set_return_value(&V8::String::new_from_slice(&signed));
+    }
+}
+
+/// Checks a signed cookie's signature, returning its value if the signature is valid, and `null`
+/// otherwise. Returns `undefined` if the app has no secret.
+#[v8_fn]
+pub fn verify_cookie(args: FunctionCallbackInfo) {
+    let name = args.get(0).unwrap().as_rust_string();
+    let signed = args.get(1).unwrap().as_rust_string();
+    let (value, given) = match signed.rfind('.') {
+        Some(dot) => (&signed[..dot], &signed[dot + 1..]),
+        None => ("", ""),
+    };
+    let mac = match cookie_mac(&name, value) {
+        Some(mac) => mac,
+        None => return,
+    };
+    // The comparison takes the same time however much of the signature matches
+    let verified = base64::decode_config(given, base64::URL_SAFE_NO_PAD)
+        .map(|given| mac.verify_slice(&given).is_ok())
+        .unwrap_or(false);
+    if verified {
+        args.set_return_value(&V8::String::new_from_slice(value));
+    } else {
+        args.set_return_value(&Isolate::null());
+    }
+}
+
+/// The URL-safe base64 of the HMAC-SHA256 of `name=value`, or `None` if there's no secret.
+fn signature(name: &str, value: &str) -> Option<std::string::String> {
+    cookie_mac(name, value)
+        .map(|mac| base64::encode_config(&mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD))
+}
+
+fn cookie_mac(name: &str, value: &str) -> Option<CookieMac> {
+    SECRET.with(|s| {
+        s.borrow().as_ref().map(|secret| {
+            let mut mac = CookieMac::new_from_slice(secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(format!("{}={}", name, value).as_bytes());
+            mac
+        })
+    })
+}
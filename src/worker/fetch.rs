@@ -1,55 +1,182 @@
 use futures::stream::Stream;
 use futures::sync::oneshot;
-use futures::Future;
+use futures::{Async, Future, Poll};
 
-use hyper::header::USER_AGENT;
-use hyper::{Body, Client, Method, Request};
-use hyper_tls::HttpsConnector;
+use hyper::header::{
+    AUTHORIZATION, CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_LOCATION,
+    CONTENT_TYPE, COOKIE, HOST, LOCATION, PROXY_AUTHORIZATION, USER_AGENT,
+};
+use hyper::{Body, Chunk, Method, Request};
 
 use futures::sink::Sink;
 use tokio::runtime::current_thread;
+use tokio::timer::Delay;
+
+use std::time::{Duration, Instant};
+
+use url::Url;
 
 use super::NULL;
 use super::*;
 
+/// The most redirects a fetch follows, as in browsers.
+static MAX_REDIRECTS: usize = 20;
+
 thread_local! {
     static FETCH_ID_TO_TX: RefCell<HashMap<i32, body::Sender>> = RefCell::new(HashMap::new());
 }
+thread_local! {
+    static FETCH_ID_TO_ABORT: RefCell<HashMap<i32, oneshot::Sender<()>>> = RefCell::new(HashMap::new());
+}
+thread_local! {
+    static OUTBOUND_TIMEOUT: RefCell<Option<Duration>> = RefCell::new(None);
+}
 lazy_thread_local!(FETCH_CB, set_fetch_cb, Persistent<V8::Function>);
+lazy_thread_local!(FETCH_DRAIN_CB, set_fetch_drain_cb, Persistent<V8::Function>);
 
-macro_rules! fetch_outbound {
-    ($fn_name:ident, $client:expr) => {
-        pub fn $fn_name(
-            req: Request<Body>,
-            tx: ResponseResultSender,
-        ) -> impl Future<Item = (), Error = ()> {
-            $client
-                .request(req)
-                .map_err(|e| {
-                    // TODO There should be an error sent to the caller.
-                    log_osgood_error!("Outbound fetch: {}", e);
-                    format!("{}", e)
-                })
-                .then(move |result| {
-                    let _ = tx.send(result);
-                    future::ok(())
-                })
-        }
-    };
+/// Aborts all outbound fetches, along with the request bodies which are still being streamed.
+pub fn abort_fetches() {
+    FETCH_ID_TO_TX.with(|cell| cell.borrow_mut().clear());
+    FETCH_ID_TO_ABORT.with(|cell| cell.borrow_mut().clear());
+}
+
+/// Sets how long each outbound fetch made by this thread's worker may take.
+pub fn set_outbound_timeout(timeout: Option<Duration>) {
+    OUTBOUND_TIMEOUT.with(|t| {
+        *t.borrow_mut() = timeout;
+    });
+}
+
+/// Sends an outbound request through the shared connection pools, and hands the response back to
+/// the worker.
+pub fn fetch_outbound(
+    req: Request<Body>,
+    tx: ResponseResultSender,
+) -> impl Future<Item = (), Error = ()> {
+    let mut request = pool::request(req);
+    let mut tx = Some(tx);
+    future::poll_fn(move || -> Poll<(), ()> {
+        let result = match request.poll() {
+            // The worker drops its end when the fetch is aborted. Returning then drops the
+            // request, which cancels it.
+            Ok(Async::NotReady) => return tx.as_mut().unwrap().poll_cancel(),
+            Ok(Async::Ready(response)) => Ok(response),
+            Err(e) => {
+                // TODO There should be an error sent to the caller.
+                log_osgood_error!("Outbound fetch: {}", e);
+                Err(format!("{}", e))
+            }
+        };
+        let _ = tx.take().unwrap().send(result);
+        Ok(Async::Ready(()))
+    })
 }
-fetch_outbound!(fetch_http_outbound, Client::new());
-fetch_outbound!(
-    fetch_https_outbound,
-    Client::builder().build(HttpsConnector::new(4).unwrap())
-);
 
 #[derive(Debug, PartialEq)]
 enum FetchBodyType {
     Stream,
     String,
+    /// An ArrayBuffer, holding the bytes of any binary body given in full.
+    Bytes,
     None,
 }
 
+/// How a fetch handles redirect responses, as given by the request's `redirect` option.
+#[derive(Clone, Copy, PartialEq)]
+enum RedirectMode {
+    Follow,
+    Manual,
+    Error,
+}
+
+/// The request body, as far as it's needed for sending the request again.
+enum OutboundBody {
+    Empty,
+    Bytes(Vec<u8>),
+    /// A streamed body, which can't be sent again.
+    Streamed,
+}
+
+/// What's needed to send a fetch again when following a redirect.
+struct Outbound {
+    redirect: RedirectMode,
+    method: Method,
+    url: std::string::String,
+    headers: HeaderMap,
+    body: OutboundBody,
+    /// The number of redirects followed so far.
+    redirects: usize,
+}
+
+impl Outbound {
+    /// Builds the request which follows a redirect to the location. Like the request it follows,
+    /// it has to pass the route's policies.
+    fn redirect_to(
+        &mut self,
+        status: StatusCode,
+        location: &str,
+    ) -> Result<Request<Body>, std::string::String> {
+        if self.redirects >= MAX_REDIRECTS {
+            return Err(format!(
+                "Too many redirects, the limit is {}: {}",
+                MAX_REDIRECTS, self.url
+            ));
+        }
+        let previous = Url::parse(&self.url).map_err(|e| format!("{}", e))?;
+        let url = previous
+            .join(location)
+            .map_err(|e| format!("Invalid redirect location {}: {}", location, e))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(format!("Unsupported redirect protocol: {}", url));
+        }
+
+        // These redirects turn the request into a GET without a body
+        let see_other = status == StatusCode::SEE_OTHER && self.method != Method::HEAD;
+        let post_redirect = (status == StatusCode::MOVED_PERMANENTLY
+            || status == StatusCode::FOUND)
+            && self.method == Method::POST;
+        if see_other || post_redirect {
+            self.method = Method::GET;
+            self.body = OutboundBody::Empty;
+            for name in &[
+                CONTENT_TYPE,
+                CONTENT_LENGTH,
+                CONTENT_ENCODING,
+                CONTENT_LANGUAGE,
+                CONTENT_LOCATION,
+            ] {
+                self.headers.remove(name);
+            }
+        }
+        let body = match &self.body {
+            OutboundBody::Empty => Body::empty(),
+            OutboundBody::Bytes(body) => Body::from(body.clone()),
+            OutboundBody::Streamed => {
+                return Err(format!(
+                    "Unable to follow a redirect which requires sending a streamed body again: {}",
+                    url
+                ))
+            }
+        };
+        // Credentials are only for the origin they were given to
+        if url.origin() != previous.origin() {
+            for name in &[AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, HOST] {
+                self.headers.remove(name);
+            }
+        }
+
+        policies::check_outbound(self.method.as_str(), url.as_str(), &self.headers)?;
+
+        self.url = url.into_string();
+        self.redirects += 1;
+        let mut request = Request::new(body);
+        *request.headers_mut() = self.headers.clone();
+        *request.method_mut() = self.method.clone();
+        *request.uri_mut() = self.url.parse().map_err(|e| format!("{}", e))?;
+        Ok(request)
+    }
+}
+
 #[v8_fn]
 pub fn set_fetch_handler(args: FunctionCallbackInfo) {
     let func = args.get(0).unwrap().to_function();
@@ -60,7 +187,7 @@ pub fn call_fetch_handler(context: Local<V8::Context>, args: Vec<&IntoValue>) {
     let null = Isolate::null();
     FETCH_CB.with(|cb| {
         let mut cb: Local<V8::Function> = cb.borrow().unwrap().into();
-        cb.call(context, &null, args);
+        watchdog::executing(None, || cb.call(context, &null, args));
     });
 }
 
@@ -72,6 +199,7 @@ pub fn start_fetch(args: FunctionCallbackInfo) {
     let body_type = match type_string.as_str() {
         "string" => FetchBodyType::String,
         "stream" => FetchBodyType::Stream,
+        "bytes" => FetchBodyType::Bytes,
         _ => FetchBodyType::None,
     };
     if body_type == FetchBodyType::Stream {
@@ -84,18 +212,39 @@ pub fn start_fetch(args: FunctionCallbackInfo) {
                 });
                 return;
             }
-            FETCH_ID_TO_TX.with(|cell| {
+            // Chunks are always converted to ArrayBuffers before being passed along
+            let chunk = v8_chunk.to_array_buffer().as_vec_u8();
+            let unsent = FETCH_ID_TO_TX.with(|cell| {
                 let mut m = cell.borrow_mut();
                 let sender = m.get_mut(&fetch_id).unwrap();
-                let _ = sender.send_data(v8_chunk.as_rust_string().into());
+                sender.send_data(chunk.into()).err()
             });
+            // When the chunk doesn't fit yet, the worker waits for it to be sent before writing more
+            let sent = match unsent {
+                Some(chunk) => {
+                    current_thread::spawn(send_when_ready(fetch_id, chunk));
+                    0
+                }
+                None => 1,
+            };
+            args.set_return_value(&Number::new(f64::from(sent)));
             return;
         }
+        // The fetch was aborted while its body was being streamed
+        match args.get(0).unwrap().type_of() {
+            TypeofTypes::String => {}
+            _ => return,
+        }
     }
     let v8_url_string = args.get(0).unwrap().to_string();
     let v8_headers = args.get(1).unwrap().to_object().get(context, "_headers");
     let v8_method = args.get(2).unwrap().as_rust_string();
-    let v8_body_string = args.get(3).unwrap();
+    let v8_body = args.get(3).unwrap();
+    let redirect = match args.get(6).unwrap().as_rust_string().as_str() {
+        "manual" => RedirectMode::Manual,
+        "error" => RedirectMode::Error,
+        _ => RedirectMode::Follow,
+    };
 
     let mut header_map = headers::rust_headers(v8_headers, context);
     if !header_map.contains_key(USER_AGENT) {
@@ -105,16 +254,23 @@ pub fn start_fetch(args: FunctionCallbackInfo) {
         );
     }
 
-    let body = match body_type {
-        FetchBodyType::String => Body::from(v8_body_string.as_rust_string()),
+    let (body, outbound_body) = match body_type {
+        FetchBodyType::String => {
+            let body = v8_body.as_rust_string().into_bytes();
+            (Body::from(body.clone()), OutboundBody::Bytes(body))
+        }
+        FetchBodyType::Bytes => {
+            let body = v8_body.to_array_buffer().as_vec_u8();
+            (Body::from(body.clone()), OutboundBody::Bytes(body))
+        }
         FetchBodyType::Stream => {
             let (sender, body) = Body::channel();
             FETCH_ID_TO_TX.with(|cell| {
                 cell.borrow_mut().insert(fetch_id, sender);
             });
-            body
+            (body, OutboundBody::Streamed)
         }
-        FetchBodyType::None => Body::empty(),
+        FetchBodyType::None => (Body::empty(), OutboundBody::Empty),
     };
 
     let mut request = Request::new(body);
@@ -123,40 +279,156 @@ pub fn start_fetch(args: FunctionCallbackInfo) {
     let outbound_url = v8_url_string.as_rust_string();
     *request.uri_mut() = outbound_url.parse().unwrap();
 
-    if !policies::policy_check(&v8_method, &outbound_url, &header_map) {
-        // TODO: Replace violation URL host with guilty host header
-        let outbound_url = outbound_url.as_str();
-        let outbound_url = match outbound_url.find('#') {
-            Some(index) => outbound_url.split_at(index).0,
-            None => outbound_url,
-        };
-        let outbound_url = match outbound_url.find('?') {
-            Some(index) => outbound_url.split_at(index).0,
-            None => outbound_url,
-        };
-        let error = format!("POLICY_VIOLATION [ {} {} ]", v8_method, outbound_url);
+    if let Err(error) = policies::check_outbound(&v8_method, &outbound_url, &header_map) {
         call_fetch_handler(context, vec![&error, &NULL, &NULL, &fetch_id]);
         return;
     }
+    let outbound = Outbound {
+        redirect,
+        method: request.method().clone(),
+        url: outbound_url,
+        headers: header_map,
+        body: outbound_body,
+        redirects: 0,
+    };
 
-    current_thread::spawn(future::lazy(move || {
-        let (oneshot_tx, oneshot_rx) = oneshot::channel();
-        FETCH_TX.with(move |tx| {
-            tx.borrow_mut()
-                .as_mut()
-                .unwrap()
-                .clone()
-                .send((request, oneshot_tx))
-                .then(move |_| {
-                    oneshot_rx
-                        .then(move |res| handle_outbound_response(res.expect("response"), fetch_id))
-                })
-        })
+    let (abort_tx, abort_rx) = oneshot::channel();
+    FETCH_ID_TO_ABORT.with(|cell| {
+        cell.borrow_mut().insert(fetch_id, abort_tx);
+    });
+    let fetch = future::lazy(move || send_following(request, outbound))
+        .then(move |res| handle_outbound_response(res, fetch_id));
+    // Aborting drops the fetch, including the response body if it's being streamed.
+    let fetch = fetch.select2(abort_rx).then(|_| Ok::<(), ()>(()));
+    let timeout: Box<Future<Item = (), Error = ()>> = match OUTBOUND_TIMEOUT.with(|t| *t.borrow()) {
+        Some(timeout) => Box::new(Delay::new(Instant::now() + timeout).then(move |_| {
+            FETCH_ID_TO_TX.with(|cell| cell.borrow_mut().remove(&fetch_id));
+            let error = format!("Outbound fetch timed out after {}ms", timeout.as_millis());
+            let context = get_context();
+            handle_scope!({
+                // The name tells the worker which kind of error to reject the fetch with
+                call_fetch_handler(
+                    context,
+                    vec![&error, &NULL, &NULL, &fetch_id, &"TimeoutError"],
+                );
+            });
+            Ok(())
+        })),
+        None => Box::new(future::empty()),
+    };
+    current_thread::spawn(fetch.select2(timeout).then(move |_| {
+        FETCH_ID_TO_ABORT.with(|cell| cell.borrow_mut().remove(&fetch_id));
+        Ok(())
     }));
 }
 
+/// Sends a chunk of a request body once its channel has room for it, and then tells the worker
+/// that it can write more. If the fetch has failed or been aborted, the chunk is dropped.
+fn send_when_ready(fetch_id: i32, chunk: Chunk) -> impl Future<Item = (), Error = ()> {
+    let mut chunk = Some(chunk);
+    future::poll_fn(move || -> Poll<(), ()> {
+        FETCH_ID_TO_TX.with(|cell| {
+            if let Some(sender) = cell.borrow_mut().get_mut(&fetch_id) {
+                match sender.poll_ready() {
+                    Ok(Async::Ready(())) => {
+                        let _ = sender.send_data(chunk.take().unwrap());
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(_) => {}
+                }
+            }
+            Ok(Async::Ready(()))
+        })
+    })
+    .map(move |()| {
+        let context = get_context();
+        handle_scope!({
+            let null = Isolate::null();
+            FETCH_DRAIN_CB.with(|cb| {
+                let mut cb = cb.borrow().unwrap().into_local();
+                watchdog::executing(None, || cb.call(context, &null, vec![&fetch_id]));
+            });
+        });
+    })
+}
+
+#[v8_fn]
+pub fn set_fetch_drain_handler(args: FunctionCallbackInfo) {
+    let func = args.get(0).unwrap().to_function();
+    set_fetch_drain_cb(func.into());
+}
+
+/// Cancels a fetch. Nothing more is delivered to the fetch handler for it.
+#[v8_fn]
+pub fn abort_fetch(args: FunctionCallbackInfo) {
+    let fetch_id = args.get(0).unwrap().to_number().value() as i32;
+    FETCH_ID_TO_TX.with(|cell| cell.borrow_mut().remove(&fetch_id));
+    if let Some(abort_tx) = FETCH_ID_TO_ABORT.with(|cell| cell.borrow_mut().remove(&fetch_id)) {
+        let _ = abort_tx.send(());
+    }
+}
+
+/// Hands a request to the runtime which makes outbound requests, and waits for its response.
+fn send(request: Request<Body>) -> impl Future<Item = Response<Body>, Error = std::string::String> {
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    FETCH_TX.with(move |tx| {
+        tx.borrow_mut()
+            .as_mut()
+            .unwrap()
+            .clone()
+            .send((request, oneshot_tx))
+            .then(move |_| oneshot_rx.then(|res| res.expect("response")))
+    })
+}
+
+/// Sends a request, and follows the redirects it gets as far as its redirect mode allows.
+fn send_following(
+    request: Request<Body>,
+    mut outbound: Outbound,
+) -> Box<Future<Item = (Response<Body>, Outbound), Error = std::string::String>> {
+    Box::new(send(request).and_then(move |response| {
+        let status = response.status();
+        let location = match response.headers().get(LOCATION) {
+            Some(location) if is_redirect(status) => location,
+            _ => return Either::A(future::ok((response, outbound))),
+        };
+        match outbound.redirect {
+            RedirectMode::Manual => return Either::A(future::ok((response, outbound))),
+            RedirectMode::Error => {
+                return Either::A(future::err(format!(
+                    "Redirect not allowed: {} {}",
+                    status.as_u16(),
+                    outbound.url
+                )))
+            }
+            RedirectMode::Follow => {}
+        }
+        let next = match location.to_str() {
+            Ok(location) => outbound.redirect_to(status, location),
+            Err(_) => Err(format!("Invalid redirect location from {}", outbound.url)),
+        };
+        match next {
+            Ok(request) => Either::B(send_following(request, outbound)),
+            Err(e) => Either::A(future::err(e)),
+        }
+    }))
+}
+
+/// Only these statuses are redirects, according to the Fetch spec. Other 3xx responses, like
+/// `300 Multiple Choices`, are returned as they are, even when they have a `Location` header.
+fn is_redirect(status: StatusCode) -> bool {
+    match status {
+        StatusCode::MOVED_PERMANENTLY
+        | StatusCode::FOUND
+        | StatusCode::SEE_OTHER
+        | StatusCode::TEMPORARY_REDIRECT
+        | StatusCode::PERMANENT_REDIRECT => true,
+        _ => false,
+    }
+}
+
 fn handle_outbound_response(
-    res: ResponseResult,
+    res: Result<(Response<Body>, Outbound), std::string::String>,
     fetch_id: i32,
 ) -> Box<Future<Item = (), Error = ()>> {
     let context = get_context();
@@ -167,9 +439,11 @@ fn handle_outbound_response(
         });
         return Box::new(future::ok(()));
     }
-    let res = res.unwrap();
+    let (res, outbound) = res.unwrap();
     handle_scope!({
         let mut meta = V8::Object::new();
+        meta.set("url", outbound.url);
+        meta.set("redirects", outbound.redirects as i32);
         meta.set("status", res.status().as_u16());
         let status_string = res.status().canonical_reason().unwrap().to_string();
         meta.set("statusText", status_string);
@@ -1,22 +1,45 @@
 use super::*;
+use hyper::header::HeaderValue;
 use osgood_v8::wrapper::{Local, Valuable};
 use osgood_v8::V8;
 
-pub fn v8_headers(header_map: &HeaderMap) -> Local<V8::Object> {
-    let mut v8_headers = V8::Object::new();
-    for (h_name, h_value) in header_map {
-        v8_headers.set(h_name.as_str(), h_value.to_str().unwrap());
+/// Converts headers into a list of `[name, value]` pairs, in order, so that repeated headers such
+/// as `Set-Cookie` are kept apart.
+pub fn v8_headers(header_map: &HeaderMap) -> Local<V8::Array> {
+    let v8_headers = V8::Array::new(header_map.len() as i32);
+    let mut v8_list = v8_headers.as_value().to_object();
+    for (i, (h_name, h_value)) in header_map.iter().enumerate() {
+        let mut v8_pair = V8::Array::new(2).as_value().to_object();
+        v8_pair.set("0", h_name.as_str());
+        v8_pair.set("1", latin1_string(h_value.as_bytes()));
+        v8_list.set(&i.to_string(), v8_pair);
     }
     v8_headers
 }
 
+/// Converts a list of `[name, value]` pairs, as kept by `Headers`, into headers. Repeated names
+/// are appended rather than replaced.
 pub fn rust_headers(v8_headers: Local<V8::Value>, context: Local<V8::Context>) -> HeaderMap {
     let mut header_map = HeaderMap::new();
-    for (h_name, h_value) in v8_headers.to_object().iter(context) {
-        header_map.insert(
-            HeaderName::from_bytes((&h_name.as_rust_string()).as_bytes()).unwrap(),
-            (&h_value.as_rust_string()).parse().unwrap(),
+    for (_, v8_pair) in v8_headers.to_object().iter(context) {
+        let mut v8_pair = v8_pair.to_object();
+        let h_name = v8_pair.get(context, "0").as_rust_string();
+        let h_value = latin1_bytes(&v8_pair.get(context, "1").as_rust_string());
+        // Names and values were validated by `Headers`
+        header_map.append(
+            HeaderName::from_bytes(h_name.as_bytes()).unwrap(),
+            HeaderValue::from_bytes(&h_value).unwrap(),
         );
     }
     header_map
 }
+
+/// Header values are bytes, which JS represents as strings with a character for each byte. Bytes
+/// which aren't ASCII would otherwise be mangled, or fail to convert.
+fn latin1_string(bytes: &[u8]) -> std::string::String {
+    bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+fn latin1_bytes(string: &str) -> Vec<u8> {
+    string.chars().map(|c| c as u32 as u8).collect()
+}
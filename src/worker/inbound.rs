@@ -1,5 +1,23 @@
 use super::*;
-use hyper::header::HeaderValue;
+use crate::router::Params;
+use hyper::header::{HeaderValue, SET_COOKIE};
+
+use futures::stream;
+use futures::task::{self, Task};
+use futures::{Async, Poll};
+use hyper::Chunk;
+use tokio::timer::Interval;
+
+/// How often to check whether in-flight requests have been answered, when waiting for them.
+static DRAIN_POLL_INTERVAL_MS: u64 = 50;
+/// How long a replaced worker waits for its requests in flight, such as event streams which never
+/// end on their own, before failing them.
+static RELOAD_DRAIN_TIMEOUT_MS: u64 = 10_000;
+/// How often to check whether the client of an event stream is still connected, when the stream
+/// has no heartbeat.
+static DISCONNECT_POLL_INTERVAL_MS: u64 = 1000;
+/// An SSE comment, which clients ignore but which keeps proxies from closing idle connections.
+static HEARTBEAT: &str = ":\n\n";
 
 enum ResponseHolder {
     Resp(body::Sender),
@@ -12,66 +30,351 @@ thread_local! {
 thread_local! {
     static NEXT_REQ_ID: RefCell<i32> = RefCell::new(0);
 }
+thread_local! {
+    /// The tasks watching for each request's client to disconnect. They're woken whenever the
+    /// request's entry in `REQ_ID_TO_TX` changes.
+    static DISCONNECT_WATCHERS: RefCell<HashMap<i32, Task>> = RefCell::new(HashMap::new());
+}
+thread_local! {
+    /// The responses with a chunk waiting for room in their body's channel. Only the task sending
+    /// the chunk polls their senders meanwhile, since a sender only wakes the task which polled it
+    /// last.
+    static PENDING_WRITES: RefCell<HashSet<i32>> = RefCell::new(HashSet::new());
+}
+thread_local! {
+    /// The request bodies which are paused until the worker has read more of them. Dropping the
+    /// sender stops delivering the body altogether.
+    static BODY_PULLS: RefCell<HashMap<i32, oneshot::Sender<()>>> = RefCell::new(HashMap::new());
+}
 lazy_thread_local!(HEAD_CB, set_head_cb, Persistent<V8::Function>);
+lazy_thread_local!(ABORT_CB, set_abort_cb, Persistent<V8::Function>);
+lazy_thread_local!(DRAIN_CB, set_drain_cb, Persistent<V8::Function>);
+lazy_thread_local!(
+    EVENT_STREAM_CB,
+    set_event_stream_cb,
+    Persistent<V8::Function>
+);
 
-pub fn handle_inbound((req, tx): Message, origin: &str) -> impl Future<Item = (), Error = ()> {
+pub fn handle_inbound((req, tx): Message) -> impl Future<Item = (), Error = ()> {
     let req_id = get_next_req_id();
     REQ_ID_TO_TX.with(|cell| {
         cell.borrow_mut().insert(req_id, ResponseHolder::Tx(tx));
     });
-    let body_handler: Persistent<V8::Function>;
+    watchdog::arm(req_id);
+    current_thread::spawn(watch_disconnect(req_id));
+    let body_handler: Option<Persistent<V8::Function>>;
     let mut context = get_context();
     handle_scope!({
         let worker_handler = context.global().get_private(context, "worker_handler");
         let method = req.method().to_string();
-        let mut uri = std::string::String::new();
-        uri.push_str(&origin);
-        uri.push_str(&req.uri().to_string());
+        let uri = req.uri().to_string();
         let v8_headers = headers::v8_headers(req.headers());
-        body_handler = call_inbound_req_head_handler(
-            context,
-            vec![&req_id, &worker_handler, &method, &uri, &v8_headers],
-        )
-        .into();
+        let v8_cookies = cookies::v8_cookies(req.headers());
+        let mut v8_params = V8::Object::new();
+        if let Some(Params(params)) = req.extensions().get::<Params>() {
+            for (name, value) in params {
+                v8_params.set(name, value.as_str());
+            }
+        }
+        // If the head handler was terminated, there's nothing to deliver the body to.
+        body_handler = watchdog::executing(Some(req_id), || {
+            call_inbound_req_head_handler(
+                context,
+                vec![
+                    &req_id,
+                    &worker_handler,
+                    &method,
+                    &uri,
+                    &v8_headers,
+                    &v8_params,
+                    &v8_cookies,
+                ],
+            )
+        })
+        .map(|handler| handler.into());
     });
 
+    // The end of the body is delivered as `None`
     req.into_body()
-        .for_each(move |chunk| {
-            handle_scope!({
-                let chunk = std::string::String::from_utf8(chunk.to_vec()).unwrap();
-                let null = Isolate::null();
-                let mut cb = body_handler.into_local();
-                cb.call(context, &null, vec![&chunk]);
-            });
-            future::ok(())
+        .map(Some)
+        .chain(stream::once(Ok(None)))
+        .map_err(|e| {
+            // TODO send a "Bad Request" response.
+            log_osgood_error!("Inbound request: {}", e);
+        })
+        .and_then(move |chunk| match body_handler {
+            Some(body_handler) => deliver_body_chunk(req_id, body_handler, chunk),
+            None => Box::new(future::ok(chunk.is_some())),
         })
-        .and_then(move |_| {
+        .take_while(|more| Ok(*more))
+        .for_each(|_| Ok(()))
+}
+
+/// Hands a chunk of a request's body to the worker, or tells it that the body has ended. Evaluates
+/// to whether more of the body should be delivered, which is once the worker's queue for the body
+/// has room for it. Delivery stops if the request has been answered while the queue is full.
+fn deliver_body_chunk(
+    req_id: i32,
+    body_handler: Persistent<V8::Function>,
+    chunk: Option<Chunk>,
+) -> Box<Future<Item = bool, Error = ()>> {
+    let context = get_context();
+    let chunk = match chunk {
+        Some(chunk) => chunk,
+        None => {
             handle_scope!({
                 let null = Isolate::null();
                 let mut cb = body_handler.into_local();
-                cb.call(context, &null, vec![]);
+                watchdog::executing(Some(req_id), || cb.call(context, &null, vec![]));
                 body_handler.reset();
             });
-            future::ok(())
-        })
-        .map_err(|e| {
-            // TODO send a "Bad Request" response.
-            log_osgood_error!("Inbound request: {}", e);
-        })
+            return Box::new(future::ok(false));
+        }
+    };
+    // The worker may ask for more while the chunk is being handed over
+    let (pull_tx, pull_rx) = oneshot::channel();
+    BODY_PULLS.with(|cell| cell.borrow_mut().insert(req_id, pull_tx));
+    let has_room: bool;
+    handle_scope!({
+        let chunk = ArrayBuffer::new_from_u8_array(chunk.as_ref(), chunk.len());
+        let null = Isolate::null();
+        let mut cb = body_handler.into_local();
+        has_room = watchdog::executing(Some(req_id), || cb.call(context, &null, vec![&chunk]))
+            .as_rust_bool(context);
+    });
+    let answered = REQ_ID_TO_TX.with(|cell| !cell.borrow().contains_key(&req_id));
+    if has_room || answered {
+        BODY_PULLS.with(|cell| cell.borrow_mut().remove(&req_id));
+        if !has_room {
+            body_handler.reset();
+        }
+        return Box::new(future::ok(has_room));
+    }
+    Box::new(pull_rx.then(move |pulled| {
+        if pulled.is_err() {
+            body_handler.reset();
+        }
+        Ok(pulled.is_ok())
+    }))
+}
+
+/// Resumes delivering a request's body, once the worker has read enough of it.
+#[v8_fn]
+pub fn resume_request_body(args: FunctionCallbackInfo) {
+    let req_id = args.get(0).unwrap().to_number().value() as i32;
+    if let Some(pull_tx) = BODY_PULLS.with(|cell| cell.borrow_mut().remove(&req_id)) {
+        let _ = pull_tx.send(());
+    }
 }
 
+/// Evaluates to whether the response was sent. It isn't sent if the request was already answered,
+/// e.g. because it timed out.
 macro_rules! send_response {
     ($req_id:expr, $response:expr) => {
         REQ_ID_TO_TX.with(move |cell| {
             let mut m = cell.borrow_mut();
-            let _ = match (*m).remove($req_id).unwrap() {
-                ResponseHolder::Tx(tx) => {
+            wake_disconnect_watcher(*$req_id);
+            match (*m).remove($req_id) {
+                Some(ResponseHolder::Tx(tx)) => {
+                    watchdog::disarm(*$req_id);
                     let _ = tx.send($response);
+                    true
                 }
-                _ => log_osgood_error!("sending response after end"),
-            };
+                Some(_) => {
+                    log_osgood_error!("sending response after end");
+                    false
+                }
+                None => false,
+            }
+        })
+    };
+}
+
+/// Resolves once the request has been fully answered, or its client has disconnected. Either way,
+/// the request's signal is then aborted, so that the worker can stop working on it.
+fn watch_disconnect(req_id: i32) -> impl Future<Item = (), Error = ()> {
+    future::poll_fn(move || -> Poll<bool, ()> {
+        // Whether the client disconnected, or `None` while that's still unknown
+        let disconnected = REQ_ID_TO_TX.with(|cell| match cell.borrow_mut().get_mut(&req_id) {
+            // hyper drops its end when the connection is closed
+            Some(ResponseHolder::Tx(tx)) => match tx.poll_cancel() {
+                Ok(Async::Ready(())) => Some(true),
+                _ => None,
+            },
+            // The pending write finds out about disconnects, and wakes this task afterwards
+            Some(ResponseHolder::Resp(_)) if has_pending_write(req_id) => None,
+            Some(ResponseHolder::Resp(sender)) => match sender.poll_ready() {
+                Err(_) => Some(true),
+                _ => None,
+            },
+            None => Some(false),
+        });
+        match disconnected {
+            Some(disconnected) => {
+                DISCONNECT_WATCHERS.with(|cell| cell.borrow_mut().remove(&req_id));
+                Ok(Async::Ready(disconnected))
+            }
+            None => {
+                DISCONNECT_WATCHERS.with(|cell| cell.borrow_mut().insert(req_id, task::current()));
+                Ok(Async::NotReady)
+            }
+        }
+    })
+    .map(move |disconnected| {
+        if disconnected {
+            log_info!("Client disconnected");
+            REQ_ID_TO_TX.with(|cell| cell.borrow_mut().remove(&req_id));
+            watchdog::disarm(req_id);
+        }
+        // A body which the worker isn't reading any more is no longer needed
+        BODY_PULLS.with(|cell| cell.borrow_mut().remove(&req_id));
+        let context = get_context();
+        handle_scope!({
+            let null = Isolate::null();
+            ABORT_CB.with(|cb| {
+                let mut cb = cb.borrow().unwrap().into_local();
+                watchdog::executing(Some(req_id), || cb.call(context, &null, vec![&req_id]));
+            });
         });
+    })
+}
+
+fn has_pending_write(req_id: i32) -> bool {
+    PENDING_WRITES.with(|cell| cell.borrow().contains(&req_id))
+}
+
+fn wake_disconnect_watcher(req_id: i32) {
+    DISCONNECT_WATCHERS.with(|cell| {
+        if let Some(task) = cell.borrow().get(&req_id) {
+            task.notify();
+        }
+    });
+}
+
+/// Answers a request with a 504, unless a response has already been started.
+pub fn expire_request(req_id: i32) {
+    let mut response = Response::new("worker timed out\n".into());
+    (*response.headers_mut()).insert("Content-Type", HeaderValue::from_str("text/plain").unwrap());
+    *response.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+    let is_pending = REQ_ID_TO_TX.with(|cell| match cell.borrow().get(&req_id) {
+        Some(ResponseHolder::Tx(_)) => true,
+        _ => false,
+    });
+    if is_pending {
+        send_response!(&req_id, Ok(response));
+    }
+}
+
+/// Resolves once every request has been fully answered. If that takes longer than the worker may
+/// wait, whether because it's being replaced or because the shutdown deadline has passed, the
+/// requests which are left are failed instead.
+pub fn drained() -> impl Future<Item = (), Error = ()> {
+    let deadline = Instant::now() + Duration::from_millis(RELOAD_DRAIN_TIMEOUT_MS);
+    Interval::new_interval(Duration::from_millis(DRAIN_POLL_INTERVAL_MS))
+        .take_while(move |_| {
+            let pending = REQ_ID_TO_TX.with(|cell| !cell.borrow().is_empty());
+            Ok(pending && Instant::now() < deadline && !shutdown_deadline_passed())
+        })
+        .for_each(|_| Ok(()))
+        .map_err(|e| log_osgood_error!("Waiting for in-flight requests: {}", e))
+        .then(|result| {
+            abort_requests();
+            result
+        })
+}
+
+/// Fails every request which hasn't been fully answered yet. This is used when the isolate is
+/// about to be replaced, since it can no longer finish them.
+pub fn abort_requests() {
+    let pending: Vec<(i32, ResponseHolder)> =
+        REQ_ID_TO_TX.with(|cell| cell.borrow_mut().drain().collect());
+    for (req_id, holder) in pending {
+        match holder {
+            ResponseHolder::Tx(tx) => {
+                watchdog::disarm(req_id);
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                let _ = tx.send(Ok(response));
+            }
+            // Dropping the sender aborts the response body.
+            ResponseHolder::Resp(_) => {}
+        }
+    }
+}
+
+#[v8_fn]
+pub fn set_event_stream_handler(args: FunctionCallbackInfo) {
+    let func = args.get(0).unwrap().to_function();
+    set_event_stream_cb(func.into());
+}
+
+/// Watches a response which has been started as an event stream. A heartbeat is sent every
+/// `heartbeatMs`, unless it's 0. Once the client disconnects, the response is dropped and the
+/// event stream handler is called with the request ID, so that the worker can stop producing
+/// events.
+#[v8_fn]
+pub fn watch_event_stream(args: FunctionCallbackInfo) {
+    let req_id = args.get(0).unwrap().to_number().value() as i32;
+    let heartbeat_ms = args.get(1).unwrap().to_number().value() as u64;
+    let interval_ms = match heartbeat_ms {
+        0 => DISCONNECT_POLL_INTERVAL_MS,
+        ms => ms,
     };
+    let interval = Duration::from_millis(interval_ms);
+    let task = Interval::new(Instant::now() + interval, interval)
+        .map_err(|e| log_osgood_error!("Event stream heartbeat: {}", e))
+        .take_while(move |_| {
+            let disconnected = REQ_ID_TO_TX.with(|cell| {
+                let mut m = cell.borrow_mut();
+                let sender = match m.get_mut(&req_id) {
+                    // The worker's own writes keep the connection busy
+                    Some(ResponseHolder::Resp(_)) if has_pending_write(req_id) => {
+                        return Some(false)
+                    }
+                    Some(ResponseHolder::Resp(sender)) => sender,
+                    // The worker has ended the stream
+                    _ => return None,
+                };
+                match sender.poll_ready() {
+                    Err(_) => Some(true),
+                    Ok(Async::Ready(())) => {
+                        if heartbeat_ms > 0 {
+                            let _ = sender.send_data(HEARTBEAT.into());
+                        }
+                        Some(false)
+                    }
+                    // The client isn't keeping up, so there's no need for a heartbeat
+                    Ok(Async::NotReady) => Some(false),
+                }
+            });
+            match disconnected {
+                Some(true) => {
+                    REQ_ID_TO_TX.with(|cell| cell.borrow_mut().remove(&req_id));
+                    wake_disconnect_watcher(req_id);
+                    let context = get_context();
+                    handle_scope!({
+                        let null = Isolate::null();
+                        EVENT_STREAM_CB.with(|cb| {
+                            let mut cb = cb.borrow().unwrap().into_local();
+                            watchdog::executing(Some(req_id), || {
+                                cb.call(context, &null, vec![&req_id])
+                            });
+                        });
+                    });
+                    Ok(false)
+                }
+                Some(false) => Ok(true),
+                None => Ok(false),
+            }
+        })
+        .for_each(|_| Ok(()));
+    current_thread::spawn(task);
+}
+
+#[v8_fn]
+pub fn set_request_abort_handler(args: FunctionCallbackInfo) {
+    let func = args.get(0).unwrap().to_function();
+    set_abort_cb(func.into());
 }
 
 #[v8_fn]
@@ -83,11 +386,16 @@ pub fn set_inbound_req_head_handler(args: FunctionCallbackInfo) {
 pub fn call_inbound_req_head_handler(
     context: Local<V8::Context>,
     args: Vec<&IntoValue>,
-) -> Local<V8::Function> {
+) -> Option<Local<V8::Function>> {
     let null = Isolate::null();
     HEAD_CB.with(|cb| {
         let mut cb = cb.borrow().unwrap().into_local();
-        cb.call(context, &null, args).to_function()
+        let body_handler = cb.call(context, &null, args);
+        if body_handler.is_function() {
+            Some(body_handler.to_function())
+        } else {
+            None
+        }
     })
 }
 
@@ -119,11 +427,18 @@ pub fn start_response(args: FunctionCallbackInfo) {
         .get(context, "headers")
         .to_object()
         .get(context, "_headers");
-    let has_string_body = args.length() == 3;
+    let has_string_body = args.length() == 4;
     let context = Isolate::get_current_context();
-    let header_map = headers::rust_headers(v8_headers, context);
+    let mut header_map = headers::rust_headers(v8_headers, context);
+    // Cookies set through the context are added after the response's own headers
+    for (_, v8_cookie) in args.get(2).unwrap().to_object().iter(context) {
+        header_map.append(
+            SET_COOKIE,
+            HeaderValue::from_str(&v8_cookie.as_rust_string()).unwrap(),
+        );
+    }
     if has_string_body {
-        let body = args.get(2).unwrap();
+        let body = args.get(3).unwrap();
         let mut response = Response::new(body.as_rust_string().into());
         *response.status_mut() = StatusCode::from_u16(status_code).unwrap();
         *response.headers_mut() = header_map;
@@ -133,11 +448,12 @@ pub fn start_response(args: FunctionCallbackInfo) {
         let mut response = Response::new(body);
         *response.status_mut() = StatusCode::from_u16(status_code).unwrap();
         *response.headers_mut() = header_map;
-        send_response!(&req_id, Ok(response));
-        REQ_ID_TO_TX.with(move |cell| {
-            let mut m = cell.borrow_mut();
-            m.insert(req_id, ResponseHolder::Resp(sender));
-        });
+        if send_response!(&req_id, Ok(response)) {
+            REQ_ID_TO_TX.with(move |cell| {
+                let mut m = cell.borrow_mut();
+                m.insert(req_id, ResponseHolder::Resp(sender));
+            });
+        }
     }
 }
 
@@ -149,21 +465,73 @@ pub fn write_response(args: FunctionCallbackInfo) {
     if v8_chunk.as_rust_bool(context) {
         let mut chunk = v8_chunk.to_array_buffer();
         let chunk = chunk.as_vec_u8();
-        REQ_ID_TO_TX.with(|cell| {
+        let unsent = REQ_ID_TO_TX.with(|cell| {
             let mut m = cell.borrow_mut();
-            let response_body_sender = match (*m).get_mut(&req_id).unwrap() {
-                ResponseHolder::Resp(x) => x,
-                _ => panic!("bad state"),
-            };
-            let _ = response_body_sender.send_data(chunk.into());
+            match (*m).get_mut(&req_id) {
+                Some(ResponseHolder::Resp(response_body_sender)) => {
+                    response_body_sender.send_data(chunk.into()).err()
+                }
+                Some(_) => panic!("bad state"),
+                // The request was already answered, e.g. because it timed out
+                None => None,
+            }
         });
+        // When the chunk doesn't fit yet, the worker waits for it to be sent before writing more
+        let sent = match unsent {
+            Some(chunk) => {
+                PENDING_WRITES.with(|cell| cell.borrow_mut().insert(req_id));
+                current_thread::spawn(send_when_ready(req_id, chunk));
+                0
+            }
+            None => 1,
+        };
+        args.set_return_value(&Number::new(f64::from(sent)));
     } else {
         REQ_ID_TO_TX.with(|cell| {
-            (*cell.borrow_mut()).remove(&req_id).unwrap();
+            (*cell.borrow_mut()).remove(&req_id);
         });
+        wake_disconnect_watcher(req_id);
     }
 }
 
+/// Sends a chunk of a response body once its channel has room for it, and then tells the worker
+/// that it can write more. If the client has disconnected, the chunk is dropped.
+fn send_when_ready(req_id: i32, chunk: Chunk) -> impl Future<Item = (), Error = ()> {
+    let mut chunk = Some(chunk);
+    future::poll_fn(move || -> Poll<(), ()> {
+        REQ_ID_TO_TX.with(|cell| {
+            if let Some(ResponseHolder::Resp(sender)) = cell.borrow_mut().get_mut(&req_id) {
+                match sender.poll_ready() {
+                    Ok(Async::Ready(())) => {
+                        let _ = sender.send_data(chunk.take().unwrap());
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(_) => {}
+                }
+            }
+            Ok(Async::Ready(()))
+        })
+    })
+    .map(move |()| {
+        PENDING_WRITES.with(|cell| cell.borrow_mut().remove(&req_id));
+        wake_disconnect_watcher(req_id);
+        let context = get_context();
+        handle_scope!({
+            let null = Isolate::null();
+            DRAIN_CB.with(|cb| {
+                let mut cb = cb.borrow().unwrap().into_local();
+                watchdog::executing(Some(req_id), || cb.call(context, &null, vec![&req_id]));
+            });
+        });
+    })
+}
+
+#[v8_fn]
+pub fn set_response_drain_handler(args: FunctionCallbackInfo) {
+    let func = args.get(0).unwrap().to_function();
+    set_drain_cb(func.into());
+}
+
 fn get_next_req_id() -> i32 {
     NEXT_REQ_ID.with(|id| {
         let new_id = *id.borrow();
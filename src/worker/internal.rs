@@ -66,6 +66,12 @@ impl<'a> From<std::string::String> for InternalModuleError<'a> {
     }
 }
 
+/// Forgets the compiled bootstrap modules. Must be called when the isolate they belong to is
+/// disposed.
+pub fn clear_module_cache() {
+    BOOTSTRAP_MAP.with(|map| map.borrow_mut().clear());
+}
+
 pub fn run_internal_module(
     context: Local<Context>,
     name: &str,
@@ -1,67 +1,203 @@
 use super::*;
+use serde_json::json;
 use url::Url;
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 thread_local! {
     static POLICIES: RefCell<Vec<Policy>> = RefCell::new(Vec::new())
 }
+thread_local! {
+    static HEADER_POLICIES: RefCell<Vec<HeaderPolicy>> = RefCell::new(Vec::new())
+}
+thread_local! {
+    static POLICY_MODE: RefCell<PolicyMode> = RefCell::new(PolicyMode::Enforce)
+}
+
+/// An outbound request which no policy allows.
+pub struct Violation {
+    method: std::string::String,
+    url: std::string::String,
+    /// Set when the URL is allowed, but the request's Host header points elsewhere.
+    host_header: Option<std::string::String>,
+    /// Set when the URL is allowed, but a header policy doesn't allow this header to be sent to it.
+    header: Option<std::string::String>,
+}
+
+impl Violation {
+    /// The error the fetch fails with.
+    pub fn message(&self) -> std::string::String {
+        match &self.header {
+            Some(header) => format!(
+                "POLICY_VIOLATION [ {} {} with {} header ]",
+                self.method,
+                without_query(&self.url),
+                header
+            ),
+            None => format!(
+                "POLICY_VIOLATION [ {} {} ]",
+                self.method,
+                without_query(&self.url)
+            ),
+        }
+    }
 
-pub fn set_policies(policies: Vec<Policy>) {
+    /// Describes the violation as JSON, for the log.
+    fn to_json(&self, mode: PolicyMode) -> serde_json::Value {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis() as u64)
+            .unwrap_or(0);
+        json!({
+            "event": "policy_violation",
+            "route": crate::NAME.with(|name| name.borrow().clone()),
+            "method": self.method,
+            "url": without_query(&self.url),
+            "hostHeader": self.host_header,
+            "header": self.header,
+            "mode": match mode {
+                PolicyMode::Enforce => "enforce",
+                PolicyMode::Report => "report",
+            },
+            "timestamp": timestamp,
+        })
+    }
+}
+
+pub fn set_policies(policies: Vec<Policy>, header_policies: Vec<HeaderPolicy>, mode: PolicyMode) {
     POLICIES.with(|p| {
         *p.borrow_mut() = policies;
     });
+    HEADER_POLICIES.with(|p| {
+        *p.borrow_mut() = header_policies;
+    });
+    POLICY_MODE.with(|m| {
+        *m.borrow_mut() = mode;
+    });
 }
 
-pub fn policy_check(method: &str, url: &str, header_map: &HeaderMap) -> bool {
-    if !policy_check_url(method, url) {
-        return false;
+/// Checks an outbound request against the route's policies. Violations are always logged, but
+/// only fail the request when the route enforces its policies.
+pub fn check_outbound(
+    method: &str,
+    url: &str,
+    header_map: &HeaderMap,
+) -> Result<(), std::string::String> {
+    let violation = match policy_check(method, url, header_map) {
+        Ok(()) => return Ok(()),
+        Err(violation) => violation,
+    };
+    let mode = POLICY_MODE.with(|m| *m.borrow());
+    stats::count_policy_violation();
+    log_worker_warning!("Policy violation: {}", violation.to_json(mode));
+    match mode {
+        PolicyMode::Enforce => Err(violation.message()),
+        PolicyMode::Report => Ok(()),
     }
+}
 
-    let mut failures = 0;
-    // This will match host, Host, hOSt, etc.
-    let hosts = header_map.get_all("host").iter();
+fn policy_check(method: &str, url: &str, header_map: &HeaderMap) -> Result<(), Violation> {
+    let violation = |host_header: Option<&str>, header: Option<&str>| Violation {
+        method: method.to_owned(),
+        url: url.to_owned(),
+        host_header: host_header.map(|host| host.to_owned()),
+        header: header.map(|header| header.to_owned()),
+    };
+    if !policy_check_url(method, url) {
+        return Err(violation(None, None));
+    }
 
-    let mut host_matches = false;
-    for host_override in hosts {
-        host_matches = true;
-        let new_url = swap_url_host(url, host_override.to_str().unwrap());
-        let result = policy_check_url(method, &new_url.unwrap());
+    // This will match host, Host, hOSt, etc. If any of them fail, the request isn't allowed.
+    for host_override in header_map.get_all("host").iter() {
+        let result = match host_override.to_str().map(|host| swap_url_host(url, host)) {
+            Ok(Ok(new_url)) => policy_check_url(method, &new_url),
+            // A Host header which can't be understood can't be allowed
+            _ => false,
+        };
         if !result {
-            failures += 1;
+            let host = std::string::String::from_utf8_lossy(host_override.as_bytes());
+            return Err(violation(Some(&host), None));
         }
     }
 
-    // if there are no host headers it's ok. If any host headers fail it's not ok
-    !host_matches || failures <= 0
+    if let Some(header) = header_map
+        .keys()
+        .find(|header| !policy_check_header(header.as_str(), url))
+    {
+        return Err(violation(None, Some(header.as_str())));
+    }
+    Ok(())
 }
 
+/// Headers which a header policy names may only be sent to URLs which a header policy naming them
+/// matches. Other headers may be sent anywhere the request is allowed to go.
+fn policy_check_header(header: &str, url: &str) -> bool {
+    HEADER_POLICIES.with(|p| {
+        let policies = p.borrow();
+        if !policies
+            .iter()
+            .any(|policy| policy.headers.iter().any(|name| name == header))
+        {
+            return true;
+        }
+        match Url::parse(url) {
+            Ok(url) => policies.iter().any(|policy| policy.allows(header, &url)),
+            Err(_) => false,
+        }
+    })
+}
+
+/// A request is allowed when at least one allow policy matches it, and no deny policy does. The
+/// query string and fragment aren't considered.
 fn policy_check_url(method: &str, url: &str) -> bool {
-    let url = match url.find('#') {
-        Some(index) => url.split_at(index).0,
-        None => url,
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return false,
     };
-    let url = match url.find('?') {
-        Some(index) => url.split_at(index).0,
-        None => url,
-    };
-    let mut matches = 0;
     POLICIES.with(|p| {
-        for policy in p.borrow().iter() {
-            if policy.matches(method, url) {
-                matches += 1;
-                break;
+        let policies = p.borrow();
+        let matching = policies
+            .iter()
+            .filter(|policy| policy.matches(method, &url));
+        let mut allowed = false;
+        for policy in matching {
+            if !policy.allow {
+                return false;
             }
+            allowed = true;
         }
-    });
-
-    matches > 0
+        allowed
+    })
 }
 
+/// Points the URL at the host from a Host header. If the header includes a port, the URL gets that
+/// port too.
 fn swap_url_host(
     original_url: &str,
     host_override: &str,
 ) -> Result<std::string::String, url::ParseError> {
-    let mut parsed_url = Url::parse(original_url).unwrap();
-    parsed_url.set_host(Some(host_override))?;
+    if host_override.contains(|c| c == '/' || c == '@' || c == '?' || c == '#') {
+        return Err(url::ParseError::InvalidDomainCharacter);
+    }
+    let mut parsed_url = Url::parse(original_url)?;
+    let override_url = Url::parse(&format!("{}://{}", parsed_url.scheme(), host_override))?;
+    parsed_url.set_host(override_url.host_str())?;
+    if let Some(port) = override_url.port() {
+        parsed_url
+            .set_port(Some(port))
+            .map_err(|()| url::ParseError::InvalidPort)?;
+    }
 
     Ok(parsed_url.into_string())
 }
+
+fn without_query(url: &str) -> &str {
+    let url = match url.find('#') {
+        Some(index) => url.split_at(index).0,
+        None => url,
+    };
+    match url.find('?') {
+        Some(index) => url.split_at(index).0,
+        None => url,
+    }
+}
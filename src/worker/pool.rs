@@ -0,0 +1,297 @@
+use super::*;
+use crate::config::{AddressRange, ConfigOutboundPool};
+
+use hyper::client::connect::dns::{GaiResolver, Name, Resolve};
+use hyper::client::connect::{Connect, Connected, Destination};
+use hyper::client::HttpConnector;
+use hyper::Client;
+use hyper_tls::HttpsConnector;
+
+use futures::Poll;
+
+use serde_json::json;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::timer::Interval;
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::sync::RwLock;
+
+lazy_static! {
+    static ref POOLS: RwLock<Option<Arc<Pools>>> = RwLock::new(None);
+}
+
+/// Long-lived clients for outbound fetches, so that connections are reused across fetches, and
+/// across workers. There's one client per scheme.
+struct Pools {
+    http: Client<OutboundConnector<HttpConnector<GuardedResolver>>>,
+    https: Client<OutboundConnector<HttpsConnector<HttpConnector<GuardedResolver>>>>,
+    http_stats: Arc<PoolStats>,
+    https_stats: Arc<PoolStats>,
+}
+
+/// Counters describing how a pool is being used. Comparing the number of requests with the number
+/// of connections opened shows how often connections are reused.
+#[derive(Default)]
+struct PoolStats {
+    requests: AtomicUsize,
+    in_flight: AtomicUsize,
+    connections_opened: AtomicUsize,
+    connections_open: AtomicUsize,
+}
+
+/// Builds the pools. This must be done before any outbound fetches are made, from within the
+/// runtime, since the pools' statistics are logged by a task on it.
+pub fn configure(config: &ConfigOutboundPool) {
+    let http_stats = Arc::new(PoolStats::default());
+    let https_stats = Arc::new(PoolStats::default());
+    let denied = Arc::new(config.denied_addresses.clone());
+    let resolver = GuardedResolver {
+        inner: GaiResolver::new(config.dns_threads),
+        denied: denied.clone(),
+    };
+    let tls = match native_tls::TlsConnector::new() {
+        Ok(tls) => tls,
+        Err(err) => {
+            log_osgood_error!("Unable to set up TLS for outbound fetches: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let mut https_connector = HttpConnector::new_with_resolver(resolver.clone());
+    https_connector.enforce_http(false);
+    let pools = Pools {
+        http: build_client(
+            config,
+            OutboundConnector {
+                inner: HttpConnector::new_with_resolver(resolver),
+                denied: denied.clone(),
+                stats: http_stats.clone(),
+            },
+        ),
+        https: build_client(
+            config,
+            OutboundConnector {
+                inner: HttpsConnector::from((https_connector, tls)),
+                denied,
+                stats: https_stats.clone(),
+            },
+        ),
+        http_stats,
+        https_stats,
+    };
+    *POOLS.write().unwrap() = Some(Arc::new(pools));
+
+    if let Some(interval) = config.stats_interval {
+        tokio::spawn(
+            Interval::new_interval(interval)
+                .map_err(|e| log_osgood_error!("Outbound pool statistics failed: {}", e))
+                .for_each(|_| {
+                    let pools = pools();
+                    pools.http_stats.log("http");
+                    pools.https_stats.log("https");
+                    log_osgood_message!(
+                        "Outbound policy violations: {}",
+                        stats::policy_violations()
+                    );
+                    Ok(())
+                }),
+        );
+    }
+}
+
+fn build_client<C>(config: &ConfigOutboundPool, connector: C) -> Client<C>
+where
+    C: Connect + 'static,
+{
+    Client::builder()
+        .max_idle_per_host(config.max_idle_per_host)
+        .keep_alive_timeout(config.idle_timeout)
+        .build(connector)
+}
+
+fn pools() -> Arc<Pools> {
+    POOLS
+        .read()
+        .unwrap()
+        .clone()
+        .expect("outbound pools are configured at startup")
+}
+
+/// The statistics of each pool, keyed by scheme.
+pub fn stats() -> serde_json::Value {
+    let pools = pools();
+    json!({
+        "http": pools.http_stats.to_json(),
+        "https": pools.https_stats.to_json(),
+    })
+}
+
+/// Sends an outbound request through the pool for its scheme. The request counts as in flight
+/// until its response arrives, it fails, or the returned future is dropped.
+pub fn request(req: Request<Body>) -> impl Future<Item = Response<Body>, Error = hyper::Error> {
+    let pools = pools();
+    let (stats, response) = if req.uri().scheme_str() == Some("https") {
+        (pools.https_stats.clone(), pools.https.request(req))
+    } else {
+        (pools.http_stats.clone(), pools.http.request(req))
+    };
+    stats.requests.fetch_add(1, Ordering::SeqCst);
+    stats.in_flight.fetch_add(1, Ordering::SeqCst);
+    let in_flight = InFlight(stats);
+    response.then(move |result| {
+        drop(in_flight);
+        result
+    })
+}
+
+impl PoolStats {
+    fn log(&self, scheme: &str) {
+        log_osgood_message!(
+            "Outbound {} pool: {} requests, {} in flight, {} connections opened, {} open",
+            scheme,
+            self.requests.load(Ordering::SeqCst),
+            self.in_flight.load(Ordering::SeqCst),
+            self.connections_opened.load(Ordering::SeqCst),
+            self.connections_open.load(Ordering::SeqCst)
+        );
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "requests": self.requests.load(Ordering::SeqCst),
+            "inFlight": self.in_flight.load(Ordering::SeqCst),
+            "connectionsOpened": self.connections_opened.load(Ordering::SeqCst),
+            "connectionsOpen": self.connections_open.load(Ordering::SeqCst),
+        })
+    }
+}
+
+struct InFlight(Arc<PoolStats>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Fails to resolve hostnames which resolve to a denied address. If any of a hostname's addresses
+/// is denied, none of them are used.
+#[derive(Clone)]
+struct GuardedResolver {
+    inner: GaiResolver,
+    denied: Arc<Vec<AddressRange>>,
+}
+
+impl Resolve for GuardedResolver {
+    type Addrs = std::vec::IntoIter<IpAddr>;
+    type Future = Box<Future<Item = Self::Addrs, Error = io::Error> + Send>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        let host = name.as_str().to_owned();
+        let denied = self.denied.clone();
+        Box::new(self.inner.resolve(name).and_then(move |addrs| {
+            let addrs: Vec<IpAddr> = addrs.collect();
+            match addrs.iter().find(|ip| is_denied(&denied, ip)) {
+                Some(ip) => Err(denied_error(&format!(
+                    "{} resolves to denied address {}",
+                    host, ip
+                ))),
+                None => Ok(addrs.into_iter()),
+            }
+        }))
+    }
+}
+
+fn is_denied(denied: &[AddressRange], ip: &IpAddr) -> bool {
+    denied.iter().any(|range| range.contains(ip))
+}
+
+fn denied_error(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("POLICY_VIOLATION [ {} ]", reason),
+    )
+}
+
+/// Wraps a connector to refuse hosts which are denied addresses, and to count the connections it
+/// opens, and how many of them are still open. Hostnames are checked by the `GuardedResolver`,
+/// since the connector doesn't resolve hosts which are addresses already.
+#[derive(Clone)]
+struct OutboundConnector<C> {
+    inner: C,
+    denied: Arc<Vec<AddressRange>>,
+    stats: Arc<PoolStats>,
+}
+
+impl<C> Connect for OutboundConnector<C>
+where
+    C: Connect,
+    C::Future: 'static,
+{
+    type Transport = Counted<C::Transport>;
+    type Error = Box<Error + Send + Sync>;
+    type Future = Box<Future<Item = (Self::Transport, Connected), Error = Self::Error> + Send>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let host = dst.host().trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse() {
+            if is_denied(&self.denied, &ip) {
+                let reason = format!("{} is a denied address", ip);
+                return Box::new(future::err(denied_error(&reason).into()));
+            }
+        }
+        let stats = self.stats.clone();
+        Box::new(
+            self.inner
+                .connect(dst)
+                .map(move |(inner, connected)| {
+                    stats.connections_opened.fetch_add(1, Ordering::SeqCst);
+                    stats.connections_open.fetch_add(1, Ordering::SeqCst);
+                    (Counted { inner, stats }, connected)
+                })
+                .map_err(Into::into),
+        )
+    }
+}
+
+/// A connection opened by an `OutboundConnector`, which is counted as open until it's dropped.
+struct Counted<T> {
+    inner: T,
+    stats: Arc<PoolStats>,
+}
+
+impl<T> Drop for Counted<T> {
+    fn drop(&mut self) {
+        self.stats.connections_open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<T: Read> Read for Counted<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for Counted<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Counted<T> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Counted<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
@@ -0,0 +1,102 @@
+use super::*;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::service_fn_ok;
+use hyper::Server;
+
+use serde_json::json;
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+lazy_static! {
+    /// Counters for each route, keyed by the name its worker logs with, so a route with several
+    /// instances has counters for each. They're kept when workers are restarted or reloaded, so
+    /// that they count for as long as the app has been running.
+    static ref ROUTES: Mutex<HashMap<std::string::String, RouteStats>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Default)]
+struct RouteStats {
+    /// Including the violations which were let through.
+    policy_violations: usize,
+    /// The number of times the route's isolates have been replaced after failing.
+    restarts: usize,
+}
+
+impl RouteStats {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "policyViolations": self.policy_violations,
+            "restarts": self.restarts,
+        })
+    }
+}
+
+/// Counts a policy violation against the route whose worker is running on this thread.
+pub fn count_policy_violation() {
+    update_route(|stats| stats.policy_violations += 1);
+}
+
+/// Counts a restart of the isolate running on this thread against its route.
+pub fn count_restart() {
+    update_route(|stats| stats.restarts += 1);
+}
+
+/// The number of policy violations across every route.
+pub fn policy_violations() -> usize {
+    ROUTES
+        .lock()
+        .unwrap()
+        .values()
+        .map(|stats| stats.policy_violations)
+        .sum()
+}
+
+fn update_route(update: impl FnOnce(&mut RouteStats)) {
+    let name = crate::NAME.with(|name| name.borrow().clone());
+    update(ROUTES.lock().unwrap().entry(name).or_default());
+}
+
+/// Serves the app's statistics as JSON from `/stats`, so that they can be monitored without
+/// parsing the log. It only listens on the loopback interface, since the statistics aren't meant
+/// for the app's clients.
+pub fn serve(port: u16) -> impl Future<Item = (), Error = ()> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let builder = match Server::try_bind(&addr) {
+        Ok(builder) => builder,
+        Err(err) => {
+            log_osgood_error!("Unable to serve statistics on {}: {}", addr, err);
+            std::process::exit(1);
+        }
+    };
+    let service = || {
+        service_fn_ok(|req: Request<Body>| -> Response<Body> {
+            if req.uri().path() != "/stats" {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body("not found\n".into())
+                    .unwrap();
+            }
+            Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .body(snapshot().to_string().into())
+                .unwrap()
+        })
+    };
+    builder
+        .serve(service)
+        .map_err(|e| log_osgood_error!("Statistics server failed: {}", e))
+}
+
+fn snapshot() -> serde_json::Value {
+    let routes: serde_json::Map<_, _> = ROUTES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, stats)| (name.clone(), stats.to_json()))
+        .collect();
+    json!({
+        "outboundPools": pool::stats(),
+        "routes": routes,
+    })
+}
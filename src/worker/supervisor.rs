@@ -0,0 +1,113 @@
+use super::*;
+
+use tokio::timer::Delay;
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+/// The delay before restarting a worker which failed again shortly after it was restarted. It's
+/// doubled on every further failure, up to `MAX_RESTART_DELAY_MS`.
+static MIN_RESTART_DELAY_MS: u64 = 100;
+static MAX_RESTART_DELAY_MS: u64 = 30_000;
+
+/// Runs the worker in an isolate on the current thread, and replaces the isolate whenever it
+/// crashes, fails to start or runs out of memory. Returns once the worker's channel is closed.
+///
+/// A failed worker is restarted right away. If it keeps failing, restarts are delayed with
+/// exponential backoff, and requests which arrive in the meantime are answered with a 503.
+pub fn supervise(
+    mut inbound_rx: mpsc::Receiver<Message>,
+    outbound_tx: mpsc::Sender<Message>,
+    handler: &str,
+    config_route: &ConfigRoute,
+) {
+    let mut restarts = 0;
+    let mut delay = None;
+    loop {
+        let started = Instant::now();
+        let mut runtime = current_thread::Runtime::new().expect("Failed to create worker runtime");
+        let exit = panic::catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(run_isolate(
+                &mut inbound_rx,
+                outbound_tx.clone(),
+                handler,
+                config_route,
+            ))
+        }));
+        // Dropping the runtime also drops whatever tasks the old isolate left behind.
+        drop(runtime);
+        match exit {
+            Ok(Ok(IsolateExit::StartFailed)) | Ok(Ok(IsolateExit::OutOfMemory)) => {}
+            Ok(_) => return,
+            Err(payload) => {
+                log_worker_error!("Worker crashed: {}", panic_message(&*payload));
+                dispose_crashed_isolate();
+            }
+        }
+        reset_thread_state();
+        restarts += 1;
+        stats::count_restart();
+
+        if started.elapsed() >= Duration::from_millis(MAX_RESTART_DELAY_MS) {
+            delay = None;
+        }
+        match delay {
+            None => {
+                log_info!("Restarting worker (restart #{})", restarts);
+                delay = Some(Duration::from_millis(MIN_RESTART_DELAY_MS));
+            }
+            Some(current) => {
+                log_info!(
+                    "Restarting worker in {}ms (restart #{})",
+                    current.as_millis(),
+                    restarts
+                );
+                if !reject_until(&mut inbound_rx, Instant::now() + current) {
+                    return;
+                }
+                delay = Some(std::cmp::min(
+                    current * 2,
+                    Duration::from_millis(MAX_RESTART_DELAY_MS),
+                ));
+            }
+        }
+    }
+}
+
+/// Answers every request with an error until the deadline, since there's no isolate to handle
+/// them. Returns `false` if the channel was closed in the meantime.
+fn reject_until(inbound_rx: &mut mpsc::Receiver<Message>, deadline: Instant) -> bool {
+    let mut runtime = current_thread::Runtime::new().expect("Failed to create worker runtime");
+    let rejecting = inbound_rx.for_each(|(_, tx): Message| {
+        let _ = tx.send(Err("worker is restarting".to_string()));
+        future::ok(())
+    });
+    match runtime.block_on(rejecting.select2(Delay::new(deadline))) {
+        Ok(Either::B(_)) | Err(Either::B(_)) => true,
+        _ => false,
+    }
+}
+
+/// Disposes the isolate which was running when a panic occurred. Since the panic unwound through
+/// the code which would normally tear the isolate down, that's done here instead.
+fn dispose_crashed_isolate() {
+    watchdog::stop();
+    CONTEXT.with(|c| {
+        *c.borrow_mut() = None;
+    });
+    if let Some(isolate) = ISOLATE.with(|i| i.borrow_mut().take()) {
+        isolate.exit();
+        isolate.dispose();
+    }
+}
+
+fn panic_message(payload: &(Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<std::string::String>() {
+        message
+    } else {
+        "unknown cause"
+    }
+}
@@ -10,6 +10,11 @@ thread_local! {
     static TIMER_HANDLES: RefCell<HashMap<i32, oneshot::SpawnHandle<(), ()>>> = RefCell::new(HashMap::new());
 }
 
+/// Cancels all pending timers.
+pub fn clear_timers() {
+    TIMER_HANDLES.with(|cell| cell.borrow_mut().clear());
+}
+
 #[v8_fn]
 pub fn set_timer_handler(args: FunctionCallbackInfo) {
     let mut context = get_context();
@@ -20,11 +25,11 @@ pub fn set_timer_handler(args: FunctionCallbackInfo) {
 
 pub fn call_timer_handler(mut context: Local<V8::Context>, args: Vec<&IntoValue>) {
     let null = Isolate::null();
-    context
+    let mut cb = context
         .global()
         .get_private(context, "timer_handler")
-        .to_function()
-        .call(context, &null, args);
+        .to_function();
+    watchdog::executing(None, || cb.call(context, &null, args));
 }
 
 #[v8_fn]
@@ -0,0 +1,254 @@
+use super::*;
+
+use tokio::timer::Delay;
+
+use std::sync::mpsc as std_mpsc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Guards requests against running past their route's timeout.
+///
+/// Two things happen when a request's deadline passes. A timer on the worker thread answers the
+/// request with a 504, which covers handlers that are merely waiting on something. However, a
+/// handler that's stuck in a loop blocks the worker thread's event loop, so that timer never fires.
+/// For that case a separate watchdog thread terminates the JavaScript running in the isolate, which
+/// unblocks the worker thread so that it can answer the request and resume serving.
+///
+/// Execution is only terminated while JavaScript is actually running, and only when it's the timed
+/// out request's, or when it has run for longer than the timeout itself. The latter catches
+/// JavaScript which isn't tied to a request, or whose request has already been answered, such as a
+/// loop which runs after an awaited fetch or in a timer. Terminating an idle isolate would instead
+/// kill whatever JavaScript runs next, which could belong to another request.
+pub struct Watchdog {
+    timeout: Duration,
+    isolate: Isolate,
+    state: Arc<Mutex<WatchdogState>>,
+    wake_tx: std_mpsc::Sender<()>,
+}
+
+struct WatchdogState {
+    /// This is `None` once the isolate has been disposed.
+    isolate: Option<ThreadSafeIsolate>,
+    deadlines: HashMap<i32, Instant>,
+    /// The JavaScript currently running on the worker thread, if any.
+    executing: Option<Execution>,
+    /// Whether the running JavaScript has been terminated.
+    terminated: bool,
+    /// How long any JavaScript may run for at a time.
+    budget: Duration,
+}
+
+struct Execution {
+    /// The request the JavaScript is running for, if it's known.
+    req_id: Option<i32>,
+    started: Instant,
+}
+
+lazy_thread_local!(WATCHDOG, set_watchdog, Watchdog);
+
+/// Starts a watchdog for the current worker thread's isolate. Requests are then timed out after
+/// the given duration.
+pub fn start(isolate: Isolate, timeout: Duration) {
+    let state = Arc::new(Mutex::new(WatchdogState {
+        isolate: Some(isolate.thread_safe()),
+        deadlines: HashMap::new(),
+        executing: None,
+        terminated: false,
+        budget: timeout,
+    }));
+    let (wake_tx, wake_rx) = std_mpsc::channel();
+    let (expired_tx, expired_rx) = mpsc::unbounded();
+    let name = super::super::NAME.with(|n| n.borrow().clone());
+
+    let thread_state = state.clone();
+    std::thread::spawn(move || {
+        super::super::NAME.with(|n| {
+            *n.borrow_mut() = name;
+        });
+        watch(thread_state, wake_rx, expired_tx);
+    });
+
+    // The termination itself is cancelled by `executing`, as soon as the JavaScript has unwound
+    current_thread::spawn(expired_rx.for_each(move |req_id| {
+        match req_id {
+            Some(req_id) => {
+                log_worker_error!("Request exceeded its time budget; execution was terminated");
+                inbound::expire_request(req_id);
+            }
+            None => {
+                log_worker_error!("JavaScript exceeded its time budget; execution was terminated");
+            }
+        }
+        future::ok(())
+    }));
+
+    set_watchdog(Watchdog {
+        timeout,
+        isolate,
+        state,
+        wake_tx,
+    });
+}
+
+/// Must be called before the isolate is disposed, so that the watchdog thread won't try to
+/// terminate it afterwards.
+pub fn stop() {
+    WATCHDOG.with(|w| {
+        if let Some(watchdog) = w.borrow_mut().take() {
+            watchdog.state.lock().unwrap().isolate = None;
+        }
+    });
+}
+
+/// Starts the clock on a request, if the route has a timeout.
+pub fn arm(req_id: i32) {
+    WATCHDOG.with(|w| {
+        if let Some(watchdog) = w.borrow().as_ref() {
+            let deadline = Instant::now() + watchdog.timeout;
+            watchdog
+                .state
+                .lock()
+                .unwrap()
+                .deadlines
+                .insert(req_id, deadline);
+            let _ = watchdog.wake_tx.send(());
+
+            current_thread::spawn(Delay::new(deadline).then(move |_| {
+                inbound::expire_request(req_id);
+                future::ok(())
+            }));
+        }
+    });
+}
+
+/// Stops the clock on a request, once its response has started.
+pub fn disarm(req_id: i32) {
+    WATCHDOG.with(|w| {
+        if let Some(watchdog) = w.borrow().as_ref() {
+            watchdog.state.lock().unwrap().deadlines.remove(&req_id);
+        }
+    });
+}
+
+/// Runs JavaScript on behalf of a request, or of no request in particular, so that the watchdog
+/// knows whether it may terminate it. Each call may run for as long as the route's timeout, and
+/// nested calls count as part of the outermost one.
+pub fn executing<T>(req_id: Option<i32>, f: impl FnOnce() -> T) -> T {
+    let watchdog = WATCHDOG.with(|w| {
+        w.borrow()
+            .as_ref()
+            .map(|watchdog| (watchdog.isolate, watchdog.state.clone()))
+    });
+    let (isolate, state) = match watchdog {
+        Some(watchdog) => watchdog,
+        None => return f(),
+    };
+    {
+        let mut state = state.lock().unwrap();
+        if state.executing.is_some() {
+            drop(state);
+            return f();
+        }
+        state.executing = Some(Execution {
+            req_id,
+            started: Instant::now(),
+        });
+    }
+    // The watchdog thread needs to start the clock on this execution's budget
+    WATCHDOG.with(|w| {
+        if let Some(watchdog) = w.borrow().as_ref() {
+            let _ = watchdog.wake_tx.send(());
+        }
+    });
+    let result = f();
+    let mut state = state.lock().unwrap();
+    state.executing = None;
+    if state.terminated {
+        // The terminated JavaScript has unwound, so the termination mustn't carry over to
+        // whatever runs next. This also clears a termination which arrived after the JavaScript
+        // had already finished on its own.
+        state.terminated = false;
+        isolate.cancel_terminate_execution();
+    }
+    result
+}
+
+fn watch(
+    state: Arc<Mutex<WatchdogState>>,
+    wake_rx: std_mpsc::Receiver<()>,
+    expired_tx: mpsc::UnboundedSender<Option<i32>>,
+) {
+    loop {
+        let next_deadline = {
+            let state = state.lock().unwrap();
+            let budget = state
+                .executing
+                .as_ref()
+                .filter(|_| !state.terminated)
+                .map(|execution| execution.started + state.budget);
+            state.deadlines.values().cloned().chain(budget).min()
+        };
+        let woken = match next_deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                let wait = if deadline > now {
+                    deadline - now
+                } else {
+                    Duration::from_millis(0)
+                };
+                match wake_rx.recv_timeout(wait) {
+                    Ok(()) => true,
+                    Err(std_mpsc::RecvTimeoutError::Timeout) => false,
+                    Err(std_mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            None => match wake_rx.recv() {
+                Ok(()) => true,
+                Err(_) => return,
+            },
+        };
+        if woken {
+            continue;
+        }
+
+        let mut state = state.lock().unwrap();
+        let now = Instant::now();
+        let isolate = match state.isolate {
+            Some(isolate) => isolate,
+            None => return,
+        };
+        // Requests which are merely waiting are answered by their `Delay`, so their deadlines are
+        // only needed while their JavaScript is running. Anything which runs for them afterwards
+        // is covered by its own budget.
+        let expired: Vec<i32> = state
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(req_id, _)| *req_id)
+            .collect();
+        for req_id in expired.iter() {
+            state.deadlines.remove(req_id);
+        }
+        let blocking = match &state.executing {
+            Some(execution) if !state.terminated => {
+                let request_expired = execution
+                    .req_id
+                    .iter()
+                    .any(|req_id| expired.contains(req_id));
+                if request_expired || execution.started + state.budget <= now {
+                    Some(execution.req_id)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if let Some(req_id) = blocking {
+            state.terminated = true;
+            isolate.terminate_execution();
+            if expired_tx.unbounded_send(req_id).is_err() {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use super::*;
+use crate::router::Params;
+
+use hyper::header::{
+    HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION,
+    UPGRADE,
+};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::WebSocketStream;
+
+use std::borrow::Cow;
+
+/// Appended to the client's key before hashing it, as required by RFC 6455.
+static ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Used when the client closed the connection without giving a status code.
+static NO_STATUS_RECEIVED: u16 = 1005;
+/// Used when the connection was lost without a closing handshake.
+static ABNORMAL_CLOSURE: u16 = 1006;
+
+thread_local! {
+    static SOCKET_ID_TO_TX: RefCell<HashMap<i32, mpsc::UnboundedSender<WsMessage>>> = RefCell::new(HashMap::new());
+}
+thread_local! {
+    static NEXT_SOCKET_ID: RefCell<i32> = RefCell::new(0);
+}
+lazy_thread_local!(OPEN_CB, set_open_cb, Persistent<V8::Function>);
+lazy_thread_local!(EVENT_CB, set_event_cb, Persistent<V8::Function>);
+
+/// Hands a request for a WebSocket route to the worker. If the worker accepts it, the connection
+/// is upgraded and its messages are exchanged with the worker until either side closes it.
+pub fn handle_inbound((req, tx): Message) -> Box<Future<Item = (), Error = ()>> {
+    let key = match upgrade_key(&req) {
+        Ok(key) => key,
+        Err(response) => {
+            let _ = tx.send(Ok(response));
+            return Box::new(future::ok(()));
+        }
+    };
+
+    let socket_id = get_next_socket_id();
+    let (outgoing_tx, outgoing_rx) = mpsc::unbounded();
+    SOCKET_ID_TO_TX.with(|cell| {
+        cell.borrow_mut().insert(socket_id, outgoing_tx);
+    });
+
+    let context = get_context();
+    let mut accepted = false;
+    handle_scope!({
+        let worker_handler = context.global().get_private(context, "worker_handler");
+        let uri = req.uri().to_string();
+        let v8_headers = headers::v8_headers(req.headers());
+        let v8_cookies = cookies::v8_cookies(req.headers());
+        let mut v8_params = V8::Object::new();
+        if let Some(Params(params)) = req.extensions().get::<Params>() {
+            for (name, value) in params {
+                v8_params.set(name, value.as_str());
+            }
+        }
+        let null = Isolate::null();
+        OPEN_CB.with(|cb| {
+            let mut cb = cb.borrow().unwrap().into_local();
+            accepted = watchdog::executing(None, || {
+                cb.call(
+                    context,
+                    &null,
+                    vec![
+                        &socket_id,
+                        &worker_handler,
+                        &uri,
+                        &v8_headers,
+                        &v8_params,
+                        &v8_cookies,
+                    ],
+                )
+            })
+            .as_rust_bool(context);
+        });
+    });
+    if !accepted {
+        SOCKET_ID_TO_TX.with(|cell| cell.borrow_mut().remove(&socket_id));
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        let _ = tx.send(Ok(response));
+        return Box::new(future::ok(()));
+    }
+
+    let response = Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(UPGRADE, "websocket")
+        .header(CONNECTION, "Upgrade")
+        .header(SEC_WEBSOCKET_ACCEPT, accept_key(&key))
+        .body(Body::empty())
+        .unwrap();
+    let _ = tx.send(Ok(response));
+
+    // The connection is only upgraded once hyper has sent the response.
+    Box::new(
+        req.into_body()
+            .on_upgrade()
+            .map_err(move |e| {
+                log_osgood_error!("WebSocket upgrade: {}", e);
+                // The worker has already accepted the socket, so it's told that it's gone
+                socket_closed(
+                    context,
+                    socket_id,
+                    ABNORMAL_CLOSURE,
+                    std::string::String::new(),
+                );
+            })
+            .and_then(move |upgraded| {
+                let (sink, stream) =
+                    WebSocketStream::from_raw_socket(upgraded, Role::Server, None).split();
+                // Once the worker closes the socket, or the isolate goes away, the sender is
+                // dropped, which ends the outgoing stream and closes the sink.
+                current_thread::spawn(
+                    sink.send_all(
+                        outgoing_rx.map_err(|()| unreachable!("unbounded receivers can't fail")),
+                    )
+                    .map(|_| ())
+                    .map_err(|e| log_info!("WebSocket send: {}", e)),
+                );
+                stream
+                    .fold(None, move |close, message| {
+                        match message {
+                            WsMessage::Text(text) => {
+                                handle_scope!({
+                                    call_event_handler(context, vec![&socket_id, &text]);
+                                });
+                            }
+                            WsMessage::Binary(data) => {
+                                handle_scope!({
+                                    let data = ArrayBuffer::new_from_u8_array(&data, data.len());
+                                    call_event_handler(context, vec![&socket_id, &data]);
+                                });
+                            }
+                            WsMessage::Close(frame) => {
+                                return Ok(frame.map(|frame| {
+                                    (u16::from(frame.code), frame.reason.into_owned())
+                                }));
+                            }
+                            // Pings are answered by tungstenite itself
+                            WsMessage::Ping(_) | WsMessage::Pong(_) => {}
+                        }
+                        Ok(close)
+                    })
+                    .then(move |result| {
+                        let (code, reason) = match result {
+                            Ok(Some(close)) => close,
+                            Ok(None) => (NO_STATUS_RECEIVED, std::string::String::new()),
+                            Err(e) => {
+                                log_info!("WebSocket receive: {}", e);
+                                (ABNORMAL_CLOSURE, std::string::String::new())
+                            }
+                        };
+                        socket_closed(context, socket_id, code, reason);
+                        Ok(())
+                    })
+            }),
+    )
+}
+
+/// Closes every socket which is still open. This is used when the isolate is about to be
+/// replaced, since it can no longer handle their messages.
+pub fn close_sockets() {
+    SOCKET_ID_TO_TX.with(|cell| cell.borrow_mut().clear());
+}
+
+/// Checks that the request is a WebSocket handshake, and returns its key. Otherwise, returns the
+/// response to send instead.
+fn upgrade_key(req: &Request<Body>) -> Result<std::string::String, Response<Body>> {
+    let header_has = |name, token: &str| {
+        req.headers().get_all(name).iter().any(|value| {
+            value
+                .to_str()
+                .unwrap_or("")
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case(token))
+        })
+    };
+    if !header_has(UPGRADE, "websocket") || !header_has(CONNECTION, "upgrade") {
+        let response = Response::builder()
+            .status(StatusCode::UPGRADE_REQUIRED)
+            .header(UPGRADE, "websocket")
+            .header(CONNECTION, "Upgrade")
+            .body("this route only accepts WebSocket connections\n".into())
+            .unwrap();
+        return Err(response);
+    }
+
+    let version = req.headers().get(SEC_WEBSOCKET_VERSION);
+    match req.headers().get(SEC_WEBSOCKET_KEY) {
+        Some(key) if version == Some(&HeaderValue::from_static("13")) => {
+            Ok(key.to_str().unwrap_or("").to_owned())
+        }
+        _ => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(SEC_WEBSOCKET_VERSION, "13")
+                .body("invalid WebSocket handshake\n".into())
+                .unwrap();
+            Err(response)
+        }
+    }
+}
+
+fn accept_key(key: &str) -> std::string::String {
+    let mut sha1 = sha1::Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(ACCEPT_GUID.as_bytes());
+    base64::encode(&sha1.digest().bytes())
+}
+
+fn get_next_socket_id() -> i32 {
+    NEXT_SOCKET_ID.with(|x| {
+        let mut socket_id = x.borrow_mut();
+        *socket_id += 1;
+        *socket_id
+    })
+}
+
+/// Forgets a socket, and delivers its close event to the worker.
+fn socket_closed(
+    context: Local<V8::Context>,
+    socket_id: i32,
+    code: u16,
+    reason: std::string::String,
+) {
+    SOCKET_ID_TO_TX.with(|cell| cell.borrow_mut().remove(&socket_id));
+    handle_scope!({
+        let null = Isolate::null();
+        call_event_handler(context, vec![&socket_id, &null, &code, &reason]);
+    });
+}
+
+fn call_event_handler(context: Local<V8::Context>, args: Vec<&IntoValue>) {
+    let null = Isolate::null();
+    EVENT_CB.with(|cb| {
+        let mut cb = cb.borrow().unwrap().into_local();
+        watchdog::executing(None, || cb.call(context, &null, args));
+    });
+}
+
+#[v8_fn]
+pub fn set_websocket_handlers(args: FunctionCallbackInfo) {
+    set_open_cb(args.get(0).unwrap().to_function().into());
+    set_event_cb(args.get(1).unwrap().to_function().into());
+}
+
+/// Queues a message for a socket. Strings are sent as text messages, and ArrayBuffers as binary
+/// messages.
+#[v8_fn]
+pub fn websocket_send(args: FunctionCallbackInfo) {
+    let socket_id = args.get(0).unwrap().to_number().value() as i32;
+    let mut v8_data = args.get(1).unwrap();
+    let message = match v8_data.type_of() {
+        TypeofTypes::String => WsMessage::Text(v8_data.as_rust_string()),
+        _ => WsMessage::Binary(v8_data.to_array_buffer().as_vec_u8()),
+    };
+    SOCKET_ID_TO_TX.with(|cell| {
+        if let Some(tx) = cell.borrow().get(&socket_id) {
+            let _ = tx.unbounded_send(message);
+        }
+    });
+}
+
+/// Starts the closing handshake for a socket. It's considered closed once the client replies.
+#[v8_fn]
+pub fn websocket_close(args: FunctionCallbackInfo) {
+    let socket_id = args.get(0).unwrap().to_number().value() as i32;
+    let code = args.get(1).unwrap().to_number().value() as u16;
+    let reason = args.get(2).unwrap().as_rust_string();
+    if let Some(tx) = SOCKET_ID_TO_TX.with(|cell| cell.borrow_mut().remove(&socket_id)) {
+        let _ = tx.unbounded_send(WsMessage::Close(Some(CloseFrame {
+            code: CloseCode::from(code),
+            reason: Cow::Owned(reason),
+        })));
+    }
+}
@@ -0,0 +1,9 @@
+app.port = 3013;
+
+// Uses the default `app.deniedAddresses`, which include loopback addresses
+app.get('/hostname', 'fetch.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/');
+});
+app.get('/literal', 'fetch.js', policy => {
+  policy.outboundHttp.allowGet('http://127.0.0.1:9001/');
+});
@@ -0,0 +1,11 @@
+export default async (request) => {
+  const url = request.url.endsWith('/literal') ?
+    'http://127.0.0.1:9001/' : 'http://localhost:9001/';
+  try {
+    const response = await fetch(url);
+    await response.text();
+    return 'allowed';
+  } catch (e) {
+    return e.message;
+  }
+};
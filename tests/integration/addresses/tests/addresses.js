@@ -0,0 +1,21 @@
+'use strict';
+const assert = require('assert');
+const {
+  test,
+  request
+} = require('./../../common.js');
+
+const PORT = 3013;
+
+test(async function deniedHostname() {
+  const [res, body] = await request(PORT, '/hostname');
+  assert.strictEqual(res.statusCode, 200);
+  assert.ok(body.toString().includes('POLICY_VIOLATION'), body.toString());
+  assert.ok(body.toString().includes('localhost resolves to denied address'), body.toString());
+});
+
+test(async function deniedLiteralAddress() {
+  const [res, body] = await request(PORT, '/literal');
+  assert.strictEqual(res.statusCode, 200);
+  assert.ok(body.toString().includes('127.0.0.1 is a denied address'), body.toString());
+});
@@ -0,0 +1,15 @@
+const aborted = [];
+
+export default async (request, context) => {
+  if (context.query.get('mode') === 'aborted') {
+    return aborted;
+  }
+
+  request.signal.addEventListener('abort', () => aborted.push('signal'));
+  try {
+    return await fetch('http://localhost:9001/slow', { signal: request.signal });
+  } catch (e) {
+    aborted.push(e.name);
+    throw e;
+  }
+};
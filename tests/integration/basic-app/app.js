@@ -1,6 +1,12 @@
 #!/usr/bin/env osgood
 
 app.port = 3000;
+app.statsPort = 3015;
+
+// The tests fetch from a server on localhost
+app.deniedAddresses = [];
+
+app.cookieSecret = 'integration tests cookie secret, not very secret';
 
 // Testing correct behavior
 app.get('/hello', 'hello.js');
@@ -14,6 +20,11 @@ app.get('/noreply', 'noreply.js');
 app.get('/echo-headers', 'echo-headers.js');
 app.get('/string-stream-resp', 'string-stream-resp.js');
 app.get('/responses/:type', 'responses.js');
+app.get('/instances', 'instance-id.js', { instances: 4 });
+app.route(['GET', 'POST'], '/echo-method', 'echo-method.js');
+app.post('/echo-body/:type', 'echo-body.js');
+app.get('/events', 'events.js');
+app.get('/cookies/:type', 'cookies.js');
 
 // Routes which talk to external services
 app.route('GET', '/urlencode', 'urlencode.js', policy => {
@@ -41,6 +52,37 @@ app.get('/fetches/:type', 'fetches.js', policy => {
   policy.outboundHttp.allowGet('http://localhost:9001/');
   policy.outboundHttp.allowPost('http://localhost:9001/echo');
 });
+app.get('/binary-bodies/:type', 'binary-bodies.js', policy => {
+  policy.outboundHttp.allowPost('http://localhost:9001/echo');
+});
+app.route(['GET', 'POST'], '/backpressure/:type', 'backpressure.js', policy => {
+  policy.outboundHttp.allowPost('http://localhost:9001/echo');
+});
+app.get('/multi-headers', 'multi-headers.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/multi-headers');
+});
+app.get('/abort', 'abort.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/slow');
+});
+app.get('/fetch-cancel/:type', 'fetch-cancel.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/slow');
+  policy.outboundHttp.timeout(300);
+});
+app.get('/redirects/:type', 'redirects.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/');
+  policy.outboundHttp.allowGet('http://localhost:9001/redirect*');
+  policy.outboundHttp.allowGet('http://localhost:9001/multiple-choices');
+  policy.outboundHttp.allowGet('http://127.0.0.1:9001/echo-headers');
+});
+app.get('/policy-precedence', 'policy-precedence.js', policy => {
+  policy.outboundHttp.allowAny('http://localhost:9001/**');
+  policy.outboundHttp.denyGet('http://localhost:9001/image.png');
+  policy.outboundHttp.allowGet('http://127.0.0.1/**');
+});
+app.get('/header-policies', 'header-policies.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/**');
+  policy.outboundHttp.allowHeaders('http://localhost:9001/', ['Authorization']);
+});
 
 // Testing incorrect/problematic behavior
 app.get('/evil', 'evil.js');
@@ -49,6 +91,12 @@ app.get('/nohandler', 'nohandler.js');
 app.get('/badhandler', 'badhandler.js');
 app.get('/syntaxerror', 'syntaxerror.js');
 app.get('/complex-bad', 'complex-bad.js');
+app.get('/infinite-loop', 'infinite-loop.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/slow');
+  policy.outboundHttp.timeout(400);
+}, { timeout: 200 });
+app.get('/out-of-memory', 'out-of-memory.js', { maxHeapMb: 64 });
+app.get('/crash', 'crash.js');
 app.get('/connection-refused', 'connection-refused.js', policy => {
   policy.outboundHttp.allowGet('http://localhost:12345');
 });
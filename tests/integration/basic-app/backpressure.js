@@ -0,0 +1,44 @@
+const CHUNK_SIZE = 64 * 1024;
+const CHUNKS = 200;
+
+const sleep = ms => new Promise(resolve => setTimeout(resolve, ms));
+
+// Produces chunks as fast as they're read
+function chunks() {
+  let sent = 0;
+  return new ReadableStream({
+    pull(controller) {
+      controller.enqueue(new Uint8Array(CHUNK_SIZE).fill(sent % 256));
+      if (++sent === CHUNKS) {
+        controller.close();
+      }
+    }
+  });
+}
+
+async function countBytes(body, delay = 0) {
+  let length = 0;
+  for await (const chunk of body) {
+    length += chunk.byteLength;
+    if (delay) {
+      await sleep(delay);
+    }
+  }
+  return length;
+}
+
+export default async (request, context) => {
+  switch (context.params.type) {
+    case 'response':
+      return new Response(chunks());
+    case 'fetch': {
+      const response = await fetch('http://localhost:9001/echo', {
+        method: 'POST',
+        body: chunks()
+      });
+      return { length: await countBytes(response.body) };
+    }
+    case 'slow-read':
+      return { length: await countBytes(request.body, 1) };
+  }
+};
@@ -0,0 +1,34 @@
+const bytes = [0, 1, 127, 128, 255];
+
+function body(type) {
+  switch (type) {
+    case 'arraybuffer':
+      return new Uint8Array(bytes).buffer;
+    case 'typedarray':
+      // Only the part of the buffer which the view covers is sent
+      return new Uint8Array([9, ...bytes, 9]).subarray(1, bytes.length + 1);
+    case 'blob':
+      return new Blob([new Uint8Array(bytes.slice(0, 2)), new Uint8Array(bytes.slice(2))], {
+        type: 'application/octet-stream'
+      });
+    case 'stream':
+      return new ReadableStream({
+        start(controller) {
+          controller.enqueue(new Uint8Array(bytes.slice(0, 3)));
+          controller.enqueue(new Uint8Array(bytes.slice(3)));
+          controller.close();
+        }
+      });
+  }
+}
+
+export default async (request, context) => {
+  const response = await fetch('http://localhost:9001/echo', {
+    method: 'POST',
+    body: body(context.params.type)
+  });
+  return {
+    bytes: [...new Uint8Array(await response.arrayBuffer())],
+    type: response.headers.get('content-type') || null
+  };
+};
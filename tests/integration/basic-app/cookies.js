@@ -0,0 +1,20 @@
+export default (request, context) => {
+  const { cookies } = context;
+  cookies.set('plain', 'hello world;', {
+    path: '/',
+    httpOnly: true,
+    sameSite: 'lax',
+    maxAge: 60
+  });
+  cookies.set('session', 'abc', { signed: true, secure: true });
+  if (context.params.type === 'string') {
+    return 'cookies';
+  }
+  return {
+    all: cookies.getAll(),
+    quoted: cookies.get('quoted'),
+    missing: cookies.get('missing') === undefined,
+    signed: cookies.get('signed', { signed: true }),
+    forged: cookies.get('forged', { signed: true }) === undefined
+  };
+};
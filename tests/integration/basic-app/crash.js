@@ -0,0 +1,7 @@
+export default request => {
+  if (request.url.endsWith('?crash')) {
+    // Status codes above 999 make the response path panic
+    return new Response('crash', { status: 1000 });
+  }
+  return 'still alive';
+};
@@ -0,0 +1,6 @@
+export default async (request, context) => {
+  if (context.params.type === 'text') {
+    return request.text();
+  }
+  return new Response(await request.arrayBuffer());
+};
@@ -0,0 +1 @@
+export default req => req.method;
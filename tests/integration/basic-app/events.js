@@ -0,0 +1,24 @@
+let disconnects = 0;
+
+export default (request, context) => {
+  const mode = context.query.get('mode');
+  if (mode === 'disconnects') {
+    return String(disconnects);
+  }
+
+  const events = new EventStream({ heartbeat: 50 });
+  if (mode === 'forever') {
+    const interval = setInterval(() => events.send('tick'), 20);
+    events.onclose = () => {
+      clearInterval(interval);
+      disconnects++;
+    };
+  } else {
+    setTimeout(() => {
+      events.send('hello');
+      events.send({ event: 'update', id: 2, data: { count: 1 } });
+      events.close();
+    }, 120);
+  }
+  return events;
+};
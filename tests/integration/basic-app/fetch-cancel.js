@@ -0,0 +1,19 @@
+const url = 'http://localhost:9001/slow';
+
+export default async (request, context) => {
+  const controller = new AbortController();
+  switch (context.params.type) {
+    case 'abort':
+      setTimeout(() => controller.abort(), 50);
+      break;
+    case 'aborted':
+      controller.abort();
+      break;
+  }
+  try {
+    await fetch(url, { signal: controller.signal });
+    return 'fetch should not have finished';
+  } catch (e) {
+    return `${e.name}: ${e.message}`;
+  }
+};
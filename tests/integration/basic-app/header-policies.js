@@ -0,0 +1,17 @@
+async function check(url, headers) {
+  try {
+    const response = await fetch(url, { headers });
+    await response.arrayBuffer();
+    return 'allowed';
+  } catch (e) {
+    return e.message;
+  }
+}
+
+export default async () => {
+  return {
+    allowed: await check('http://localhost:9001/', { authorization: 'Bearer secret' }),
+    unrestricted: await check('http://localhost:9001/image.png', { 'x-trace': 'abc' }),
+    denied: await check('http://localhost:9001/image.png', { authorization: 'Bearer secret' })
+  };
+};
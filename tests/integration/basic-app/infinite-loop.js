@@ -0,0 +1,17 @@
+export default async request => {
+  if (request.url.endsWith('?loop')) {
+    for (;;) {}
+  }
+  if (request.url.endsWith('?fetch-loop')) {
+    // The fetch outlasts the request's deadline, so the loop runs after the
+    // request has already been answered
+    await fetch('http://localhost:9001/slow').catch(() => {});
+    for (;;) {}
+  }
+  if (request.url.endsWith('?wait')) {
+    // Idle past the deadline, and then run again once it has passed
+    await new Promise(resolve => setTimeout(resolve, 400));
+    return 'too late';
+  }
+  return 'still alive';
+};
@@ -0,0 +1,4 @@
+// Each isolate evaluates this module separately, so each gets its own id
+const id = Math.random().toString(36).substring(2, 10);
+
+export default () => id;
@@ -0,0 +1,16 @@
+export default async request => {
+  const response = await fetch('http://localhost:9001/multi-headers');
+  await response.text();
+  const headers = new Headers([
+    ['Set-Cookie', 'c=3; Path=/'],
+    ['X-Latin', response.headers.get('x-latin')]
+  ]);
+  headers.append('Set-Cookie', 'd=4, e=5');
+  return new Response(JSON.stringify({
+    setCookie: response.headers.getSetCookie(),
+    via: response.headers.get('via'),
+    latin: response.headers.get('x-latin'),
+    multi: request.headers.get('x-multi'),
+    entries: [...headers]
+  }), { headers });
+};
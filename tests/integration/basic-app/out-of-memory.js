@@ -0,0 +1,10 @@
+const leak = [];
+
+export default request => {
+  if (request.url.endsWith('?leak')) {
+    for (;;) {
+      leak.push(new Array(1024 * 1024).fill(0));
+    }
+  }
+  return 'still alive';
+};
@@ -0,0 +1,20 @@
+async function check(url, init) {
+  try {
+    const response = await fetch(url, init);
+    await response.arrayBuffer();
+    return 'allowed';
+  } catch (e) {
+    return e.message;
+  }
+}
+
+export default async () => {
+  return {
+    get: await check('http://localhost:9001/'),
+    post: await check('http://localhost:9001/echo', { method: 'POST', body: 'hi' }),
+    denied: await check('http://localhost:9001/image.png'),
+    // Encoding characters which needn't be doesn't get around a deny policy
+    encoded: await check('http://localhost:9001/%69mage.png'),
+    otherPort: await check('http://127.0.0.1:9001/')
+  };
+};
@@ -0,0 +1,63 @@
+const url = 'http://localhost:9001/';
+
+export default async (request, context) => {
+  const type = context.params.type;
+  try {
+    switch (type) {
+      case 'follow': {
+        const response = await fetch(url + 'redirect');
+        return {
+          status: response.status,
+          url: response.url,
+          redirected: response.redirected,
+          body: await response.text()
+        };
+      }
+      case 'manual': {
+        const response = await fetch(url + 'redirect', { redirect: 'manual' });
+        return {
+          status: response.status,
+          location: response.headers.get('location'),
+          redirected: response.redirected
+        };
+      }
+      case 'error':
+        await fetch(url + 'redirect', { redirect: 'error' });
+        break;
+      case 'multiple-choices': {
+        const response = await fetch(url + 'multiple-choices', { redirect: 'error' });
+        return {
+          status: response.status,
+          redirected: response.redirected,
+          body: await response.text()
+        };
+      }
+      case 'forbidden':
+        await fetch(url + 'redirect-forbidden');
+        break;
+      case 'loop':
+        await fetch(url + 'redirect-loop');
+        break;
+      case 'cross-origin': {
+        const response = await fetch(url + 'redirect-cross-origin', {
+          headers: {
+            authorization: 'Bearer secret',
+            'proxy-authorization': 'Basic secret',
+            cookie: 'session=secret',
+            'x-kept': 'yes'
+          }
+        });
+        const headers = await response.json();
+        return {
+          authorization: headers.authorization || null,
+          proxyAuthorization: headers['proxy-authorization'] || null,
+          cookie: headers.cookie || null,
+          kept: headers['x-kept']
+        };
+      }
+    }
+    return { error: null };
+  } catch (e) {
+    return { error: e.message };
+  }
+};
@@ -1,5 +1,7 @@
 'use strict';
 const assert = require('assert');
+const http = require('http');
+const crypto = require('crypto');
 const {
   test,
   request,
@@ -7,6 +9,18 @@ const {
 } = require('./../../common.js');
 
 const PORT = 3000;
+const COOKIE_SECRET = 'integration tests cookie secret, not very secret';
+
+function signCookie(name, value) {
+  const signature = crypto
+    .createHmac('sha256', COOKIE_SECRET)
+    .update(`${name}=${value}`)
+    .digest('base64')
+    .replace(/=+$/, '')
+    .replace(/\+/g, '-')
+    .replace(/\//g, '_');
+  return `${value}.${signature}`;
+}
 
 test(async function hello() {
   const [res, body] = await request(PORT, '/hello');
@@ -32,6 +46,71 @@ test(async function poststream() {
   assert.deepStrictEqual({"foo1":"bar1","such":"stream"}, JSON.parse(body.toString()).fields);
 });
 
+test(async function binaryRequestBody() {
+  const bytes = Buffer.alloc(70000);
+  for (let i = 0; i < bytes.length; i++) {
+    bytes[i] = (i * 7) % 256;
+  }
+  const [res, body] = await request(PORT, '/echo-body/bytes', { method: 'POST' }, bytes);
+  assert.strictEqual(res.statusCode, 200);
+  assert.ok(body.equals(bytes));
+});
+
+test(async function requestBodyCharacterSplitAcrossChunks() {
+  const euro = Buffer.from('\u20ac');
+  const body = await new Promise((resolve, reject) => {
+    const req = http.request(`http://localhost:${PORT}/echo-body/text`, { method: 'POST' })
+      .on('response', async res => {
+        let data = '';
+        res.setEncoding('utf8');
+        res.on('data', chunk => data += chunk);
+        res.on('end', () => resolve(data));
+      })
+      .on('error', reject);
+    req.write(euro.slice(0, 1));
+    setTimeout(() => req.end(Buffer.concat([euro.slice(1), Buffer.from('!')])), 100);
+  });
+  assert.strictEqual(body, '\u20ac!');
+});
+
+async function binaryBody(type) {
+  const [res, body] = await request(PORT, `/binary-bodies/${type}`);
+  assert.strictEqual(res.statusCode, 200);
+  return JSON.parse(body.toString());
+}
+
+test(async function fetchBinaryBodies() {
+  const bytes = [0, 1, 127, 128, 255];
+  for (const type of ['arraybuffer', 'typedarray', 'stream']) {
+    assert.deepStrictEqual(await binaryBody(type), { bytes, type: null }, type);
+  }
+  assert.deepStrictEqual(await binaryBody('blob'), {
+    bytes,
+    type: 'application/octet-stream'
+  });
+});
+
+test(async function streamedResponseBackpressure() {
+  const [res, body] = await request(PORT, '/backpressure/response');
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(body.length, 200 * 64 * 1024);
+  assert.strictEqual(body[0], 0);
+  assert.strictEqual(body[body.length - 1], 199);
+});
+
+test(async function streamedFetchBodyBackpressure() {
+  const [res, body] = await request(PORT, '/backpressure/fetch');
+  assert.strictEqual(res.statusCode, 200);
+  assert.deepStrictEqual(JSON.parse(body.toString()), { length: 200 * 64 * 1024 });
+});
+
+test(async function slowlyReadRequestBody() {
+  const upload = Buffer.alloc(4 * 1024 * 1024, 'x');
+  const [res, body] = await request(PORT, '/backpressure/slow-read', { method: 'POST' }, upload);
+  assert.strictEqual(res.statusCode, 200);
+  assert.deepStrictEqual(JSON.parse(body.toString()), { length: upload.length });
+});
+
 test(async function intrinsic() {
   const [res, body] = await request(PORT, '/intrinsic');
   assert.strictEqual(res.statusCode, 200);
@@ -202,3 +281,227 @@ test(async function response_classHeader() {
   assert.strictEqual(res.statusCode, 500);
   assert.strictEqual(res.headers.testheader, 'test header value');
 });
+
+test(async function instances() {
+  const ids = new Set();
+  for (let i = 0; i < 4; i++) {
+    const [res, body] = await request(PORT, '/instances');
+    assert.strictEqual(res.statusCode, 200);
+    ids.add(body.toString());
+  }
+  assert.strictEqual(ids.size, 4);
+});
+
+test(async function methodArray() {
+  for (const method of ['GET', 'POST']) {
+    const [res, body] = await request(PORT, '/echo-method', { method });
+    assert.strictEqual(res.statusCode, 200);
+    assert.strictEqual(body.toString(), method);
+  }
+});
+
+test(async function methodNotAllowed() {
+  const [res, body] = await request(PORT, '/echo-method', { method: 'DELETE' });
+  assert.strictEqual(res.statusCode, 405);
+  assert.strictEqual(res.headers['allow'], 'GET, POST, HEAD, OPTIONS');
+});
+
+test(async function head() {
+  const [res, body] = await request(PORT, '/hello', { method: 'HEAD' });
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(res.headers['content-type'], 'text/plain');
+  assert.strictEqual(body.length, 0);
+});
+
+test(async function options() {
+  const [res, body] = await request(PORT, '/echo-method', { method: 'OPTIONS' });
+  assert.strictEqual(res.statusCode, 204);
+  assert.strictEqual(res.headers['allow'], 'GET, POST, HEAD, OPTIONS');
+});
+
+test(async function eventStream() {
+  const [res, body] = await request(PORT, '/events');
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(res.headers['content-type'], 'text/event-stream');
+  assert.strictEqual(res.headers['cache-control'], 'no-cache');
+  const text = body.toString();
+  assert.ok(text.startsWith(':\n\n'), 'expected a heartbeat before the events');
+  assert.ok(text.endsWith(
+    'data: hello\n\n' +
+    'event: update\nid: 2\ndata: {"count":1}\n\n'
+  ));
+});
+
+test(async function eventStreamDisconnect() {
+  await new Promise((resolve, reject) => {
+    http.get(`http://localhost:${PORT}/events?mode=forever`, res => {
+      res.once('data', () => {
+        res.destroy();
+        resolve();
+      });
+    }).on('error', reject);
+  });
+  await new Promise(resolve => setTimeout(resolve, 300));
+  const [res, body] = await request(PORT, '/events?mode=disconnects');
+  assert.strictEqual(body.toString(), '1');
+});
+
+test(async function requestSignalAbortsFetch() {
+  await new Promise(resolve => {
+    const req = http.get(`http://localhost:${PORT}/abort`);
+    req.on('error', () => {});
+    setTimeout(() => {
+      req.destroy();
+      resolve();
+    }, 200);
+  });
+  await new Promise(resolve => setTimeout(resolve, 300));
+  const [res, body] = await request(PORT, '/abort?mode=aborted');
+  assert.deepStrictEqual(JSON.parse(body.toString()), ['signal', 'AbortError']);
+});
+
+test(async function fetchAbortController() {
+  const [res, body] = await request(PORT, '/fetch-cancel/abort');
+  assert.strictEqual(body.toString(), 'AbortError: The operation was aborted');
+});
+
+test(async function fetchAlreadyAborted() {
+  const [res, body] = await request(PORT, '/fetch-cancel/aborted');
+  assert.strictEqual(body.toString(), 'AbortError: The operation was aborted');
+});
+
+test(async function fetchOutboundTimeout() {
+  const [res, body] = await request(PORT, '/fetch-cancel/timeout');
+  assert.strictEqual(body.toString(), 'TimeoutError: Outbound fetch timed out after 300ms');
+});
+
+async function redirects(type) {
+  const [res, body] = await request(PORT, `/redirects/${type}`);
+  assert.strictEqual(res.statusCode, 200);
+  return JSON.parse(body.toString());
+}
+
+test(async function fetchFollowsRedirects() {
+  assert.deepStrictEqual(await redirects('follow'), {
+    status: 200,
+    url: 'http://localhost:9001/',
+    redirected: true,
+    body: 'ok'
+  });
+});
+
+test(async function fetchManualRedirect() {
+  assert.deepStrictEqual(await redirects('manual'), {
+    status: 302,
+    location: '/',
+    redirected: false
+  });
+});
+
+test(async function fetchRedirectError() {
+  assert.deepStrictEqual(await redirects('error'), {
+    error: 'Redirect not allowed: 302 http://localhost:9001/redirect'
+  });
+});
+
+test(async function fetchOnlyFollowsRedirectStatuses() {
+  assert.deepStrictEqual(await redirects('multiple-choices'), {
+    status: 300,
+    redirected: false,
+    body: 'choose'
+  });
+});
+
+test(async function fetchRedirectChecksPolicies() {
+  assert.deepStrictEqual(await redirects('forbidden'), {
+    error: 'POLICY_VIOLATION [ GET http://localhost:9001/image.png ]'
+  });
+});
+
+test(async function fetchRedirectLimit() {
+  assert.deepStrictEqual(await redirects('loop'), {
+    error: 'Too many redirects, the limit is 20: http://localhost:9001/redirect-loop'
+  });
+});
+
+test(async function fetchRedirectDropsCredentials() {
+  assert.deepStrictEqual(await redirects('cross-origin'), {
+    authorization: null,
+    proxyAuthorization: null,
+    cookie: null,
+    kept: 'yes'
+  });
+});
+
+test(async function policyPrecedence() {
+  const [res, body] = await request(PORT, '/policy-precedence');
+  assert.strictEqual(res.statusCode, 200);
+  assert.deepStrictEqual(JSON.parse(body.toString()), {
+    get: 'allowed',
+    post: 'allowed',
+    denied: 'POLICY_VIOLATION [ GET http://localhost:9001/image.png ]',
+    encoded: 'POLICY_VIOLATION [ GET http://localhost:9001/%69mage.png ]',
+    otherPort: 'POLICY_VIOLATION [ GET http://127.0.0.1:9001/ ]'
+  });
+});
+
+test(async function headerPolicies() {
+  const [res, body] = await request(PORT, '/header-policies');
+  assert.strictEqual(res.statusCode, 200);
+  assert.deepStrictEqual(JSON.parse(body.toString()), {
+    allowed: 'allowed',
+    unrestricted: 'allowed',
+    denied: 'POLICY_VIOLATION [ GET http://localhost:9001/image.png with authorization header ]'
+  });
+});
+
+test(async function multiValuedHeaders() {
+  const [res, body] = await request(PORT, '/multi-headers', {
+    headers: { 'x-multi': ['1', '2'] }
+  });
+  assert.strictEqual(res.statusCode, 200);
+  assert.deepStrictEqual(res.headers['set-cookie'], ['c=3; Path=/', 'd=4, e=5']);
+  assert.strictEqual(Buffer.from(res.headers['x-latin'], 'latin1').toString('hex'), '636166e9');
+  assert.deepStrictEqual(JSON.parse(body.toString()), {
+    setCookie: ['a=1; Expires=Wed, 21 Oct 2037 07:28:00 GMT', 'b=2'],
+    via: '1.1 first, 1.1 second',
+    latin: 'café',
+    multi: '1, 2',
+    entries: [
+      ['set-cookie', 'c=3; Path=/'],
+      ['set-cookie', 'd=4, e=5'],
+      ['x-latin', 'café']
+    ]
+  });
+});
+
+test(async function cookies() {
+  const [res, body] = await request(PORT, '/cookies/json', {
+    headers: {
+      cookie: [
+        `a=1; quoted="hello%20there"; a=2; signed=${signCookie('signed', 'yes')}`,
+        `forged=${signCookie('other', 'no')}`
+      ].join('; ')
+    }
+  });
+  assert.strictEqual(res.statusCode, 200);
+  assert.ok(res.headers['content-type'].startsWith('application/json'));
+  assert.deepStrictEqual(res.headers['set-cookie'], [
+    'plain=hello%20world%3B; Max-Age=60; Path=/; HttpOnly; SameSite=Lax',
+    `session=${signCookie('session', 'abc')}; Secure`
+  ]);
+  const json = JSON.parse(body.toString());
+  assert.strictEqual(json.all.a, '1');
+  assert.strictEqual(json.quoted, 'hello there');
+  assert.strictEqual(json.missing, true);
+  assert.strictEqual(json.signed, 'yes');
+  assert.strictEqual(json.forged, true);
+});
+
+test(async function cookiesOnStringResponse() {
+  const [res, body] = await request(PORT, '/cookies/string');
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(res.headers['content-type'], 'text/plain');
+  assert.strictEqual(res.headers['set-cookie'].length, 2);
+  assert.strictEqual(body.toString(), 'cookies');
+});
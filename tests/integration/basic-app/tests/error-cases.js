@@ -6,6 +6,7 @@ const {
 } = require('./../../common.js');
 
 const PORT = 3000;
+const STATS_PORT = 3015;
 
 test(async function evil() {
   const [res, body] = await request(PORT, '/evil');
@@ -45,6 +46,65 @@ test(async function complexBad() {
   assert.strictEqual(body.toString(), '');
 });
 
+test(async function infiniteLoop() {
+  const [res, body] = await request(PORT, '/infinite-loop?loop');
+  assert.strictEqual(res.statusCode, 504);
+  assert.strictEqual(body.toString(), 'worker timed out\n');
+
+  const [res2, body2] = await request(PORT, '/infinite-loop');
+  assert.strictEqual(res2.statusCode, 200);
+  assert.strictEqual(body2.toString(), 'still alive');
+});
+
+test(async function idleTimeout() {
+  const [res, body] = await request(PORT, '/infinite-loop?wait');
+  assert.strictEqual(res.statusCode, 504);
+  assert.strictEqual(body.toString(), 'worker timed out\n');
+
+  // Timing out while idle doesn't terminate the JavaScript which runs next
+  const [res2, body2] = await request(PORT, '/infinite-loop');
+  assert.strictEqual(res2.statusCode, 200);
+  assert.strictEqual(body2.toString(), 'still alive');
+});
+
+test(async function loopAfterSlowFetch() {
+  const [res, body] = await request(PORT, '/infinite-loop?fetch-loop');
+  assert.strictEqual(res.statusCode, 504);
+  assert.strictEqual(body.toString(), 'worker timed out\n');
+
+  // The loop which starts once the fetch times out is terminated too
+  await new Promise(resolve => setTimeout(resolve, 800));
+  const [res2, body2] = await request(PORT, '/infinite-loop');
+  assert.strictEqual(res2.statusCode, 200);
+  assert.strictEqual(body2.toString(), 'still alive');
+});
+
+test(async function outOfMemory() {
+  const [res, body] = await request(PORT, '/out-of-memory?leak');
+  assert.strictEqual(res.statusCode, 500);
+  assert.strictEqual(body.toString(), '');
+
+  // The worker's isolate is replaced with a fresh one
+  const [res2, body2] = await request(PORT, '/out-of-memory');
+  assert.strictEqual(res2.statusCode, 200);
+  assert.strictEqual(body2.toString(), 'still alive');
+});
+
+test(async function crash() {
+  const [res, body] = await request(PORT, '/crash?crash');
+  assert.strictEqual(res.statusCode, 500);
+  assert.strictEqual(body.toString(), '');
+
+  // The worker is restarted after crashing
+  const [res2, body2] = await request(PORT, '/crash');
+  assert.strictEqual(res2.statusCode, 200);
+  assert.strictEqual(body2.toString(), 'still alive');
+
+  // The restart is counted
+  const [, stats] = await request(STATS_PORT, '/stats');
+  assert.strictEqual(JSON.parse(stats.toString()).routes['GET localhost/crash'].restarts, 1);
+});
+
 test(async function connectionRefused() {
   const [res, body] = await request(PORT, '/connection-refused');
   assert.strictEqual(res.statusCode, 500);
@@ -2,6 +2,7 @@
 
 const http = require('http');
 const https = require('https');
+const crypto = require('crypto');
 const assert = require('assert');
 
 const TIMEOUT = 5000;
@@ -18,6 +19,94 @@ const request = (port, url, opts = {}, reqBody = null) =>
     .end(reqBody);
   });
 
+// Opens a WebSocket connection. This is only as much of a client as the tests
+// need, so it doesn't handle fragmented or very large messages.
+const websocket = (port, url) =>
+  new Promise((resolve, reject) => {
+    http.request('http://localhost:' + port + url, {
+      headers: {
+        Connection: 'Upgrade',
+        Upgrade: 'websocket',
+        'Sec-WebSocket-Version': '13',
+        'Sec-WebSocket-Key': crypto.randomBytes(16).toString('base64')
+      }
+    })
+    .on('upgrade', (res, socket) => resolve(new WebSocketClient(socket)))
+    .on('response', res => reject(new Error(`unexpected status ${res.statusCode}`)))
+    .on('error', reject)
+    .end();
+  });
+
+class WebSocketClient {
+  constructor(socket) {
+    this.socket = socket;
+    this.buffer = Buffer.alloc(0);
+    this.frames = [];
+    this.waiting = [];
+    socket.on('data', data => {
+      this.buffer = Buffer.concat([this.buffer, data]);
+      this.parse();
+    });
+  }
+
+  parse() {
+    while (this.buffer.length >= 2) {
+      let length = this.buffer[1] & 0x7f;
+      let offset = 2;
+      if (length === 126) {
+        if (this.buffer.length < 4) {
+          return;
+        }
+        length = this.buffer.readUInt16BE(2);
+        offset = 4;
+      }
+      if (this.buffer.length < offset + length) {
+        return;
+      }
+      const frame = {
+        opcode: this.buffer[0] & 0x0f,
+        data: this.buffer.slice(offset, offset + length)
+      };
+      this.buffer = this.buffer.slice(offset + length);
+      const waiter = this.waiting.shift();
+      if (waiter) {
+        waiter(frame);
+      } else {
+        this.frames.push(frame);
+      }
+    }
+  }
+
+  // Resolves with the next frame, as { opcode, data }
+  receive() {
+    if (this.frames.length > 0) {
+      return Promise.resolve(this.frames.shift());
+    }
+    return new Promise(resolve => this.waiting.push(resolve));
+  }
+
+  send(data, opcode = typeof data === 'string' ? 0x1 : 0x2) {
+    const payload = Buffer.from(data);
+    const mask = crypto.randomBytes(4);
+    const header = Buffer.alloc(payload.length < 126 ? 2 : 4);
+    header[0] = 0x80 | opcode;
+    if (payload.length < 126) {
+      header[1] = 0x80 | payload.length;
+    } else {
+      header[1] = 0x80 | 126;
+      header.writeUInt16BE(payload.length, 2);
+    }
+    const masked = payload.map((byte, i) => byte ^ mask[i % 4]);
+    this.socket.write(Buffer.concat([header, mask, masked]));
+  }
+
+  close(code = 1000) {
+    const payload = Buffer.alloc(2);
+    payload.writeUInt16BE(code);
+    this.send(payload, 0x8);
+  }
+}
+
 async function consume(strm) {
   const chunks = [];
   for await (const chunk of strm) {
@@ -76,4 +165,5 @@ module.exports = {
   test,
   request,
   runTests,
+  websocket,
 };
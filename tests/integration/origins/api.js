@@ -0,0 +1 @@
+export default (request) => `api ${request.url}`;
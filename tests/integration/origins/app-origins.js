@@ -0,0 +1,12 @@
+app.port = 3006;
+
+app.get('/which', 'default.js');
+
+app.origin('api.example.com', origin => {
+  origin.get('/which', 'api.js');
+});
+
+app.origin('*.example.com', origin => {
+  origin.get('/which', 'wildcard.js');
+  origin.static('/static', '../static/files');
+});
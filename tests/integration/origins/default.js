@@ -0,0 +1 @@
+export default (request) => `default ${request.url}`;
@@ -0,0 +1,75 @@
+'use strict';
+const assert = require('assert');
+const net = require('net');
+const {
+  test,
+  request
+} = require('./../../common.js');
+
+const PORT = 3006;
+
+// Sends a request as raw bytes, for requests which http.request() won't send,
+// and resolves with the raw response once the server closes the connection
+const rawRequest = (port, head) =>
+  new Promise((resolve, reject) => {
+    const socket = net.connect(port, 'localhost', () => socket.write(head));
+    let data = Buffer.alloc(0);
+    socket.on('data', chunk => data = Buffer.concat([data, chunk]));
+    socket.on('end', () => resolve(data.toString('latin1')));
+    socket.on('error', reject);
+  });
+
+test(async function originDefault() {
+  const [res, body] = await request(PORT, '/which');
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(body.toString(), `default http://localhost:${PORT}/which`);
+});
+
+test(async function originExact() {
+  const [res, body] = await request(PORT, '/which', {
+    headers: { host: `api.example.com:${PORT}` }
+  });
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(body.toString(), `api http://api.example.com:${PORT}/which`);
+});
+
+test(async function originWildcard() {
+  const [res, body] = await request(PORT, '/which', {
+    headers: { host: `www.example.com:${PORT}` }
+  });
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(body.toString(), `wildcard http://www.example.com:${PORT}/which`);
+});
+
+test(async function originWildcardStatic() {
+  const [res, body] = await request(PORT, '/static/hello.css', {
+    headers: { host: `www.example.com:${PORT}` }
+  });
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(res.headers['content-type'], 'text/css');
+});
+
+test(async function originUnknown() {
+  const [res, body] = await request(PORT, '/which', {
+    headers: { host: `unknown.test:${PORT}` }
+  });
+  assert.strictEqual(res.statusCode, 404);
+});
+
+test(async function originInvalidHost() {
+  const [res, body] = await request(PORT, '/which', {
+    headers: { host: `a b.example.com:${PORT}` }
+  });
+  assert.strictEqual(res.statusCode, 400);
+
+  // HTTP/1.0 requests don't need a Host header
+  const missing = await rawRequest(PORT, 'GET /which HTTP/1.0\r\n\r\n');
+  assert.ok(/^HTTP\/1\.[01] 400 /.test(missing), missing);
+
+  // Header values may contain bytes which aren't visible ASCII
+  const nonAscii = await rawRequest(
+    PORT,
+    Buffer.from('GET /which HTTP/1.1\r\nHost: caf\xe9\r\nConnection: close\r\n\r\n', 'latin1')
+  );
+  assert.ok(/^HTTP\/1\.[01] 400 /.test(nonAscii), nonAscii);
+});
@@ -0,0 +1 @@
+export default (request) => `wildcard ${request.url}`;
@@ -0,0 +1,15 @@
+app.port = 3012;
+app.outboundPool = { statsInterval: 100 };
+app.statsPort = 3014;
+
+// The tests fetch from a server on localhost
+app.deniedAddresses = [];
+
+app.get('/fetch-three', 'fetch-three.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/');
+});
+
+// Violations are let through, but reported
+app.get('/report', 'fetch-three.js', policy => {
+  policy.outboundHttp.allowGet('http://localhost:9001/image.png');
+}, { policyMode: 'report' });
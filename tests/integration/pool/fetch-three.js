@@ -0,0 +1,7 @@
+export default async () => {
+  for (let i = 0; i < 3; i++) {
+    const response = await fetch('http://localhost:9001/');
+    await response.text();
+  }
+  return 'done';
+};
@@ -0,0 +1,100 @@
+'use strict';
+const assert = require('assert');
+const path = require('path');
+const { spawn } = require('child_process');
+const {
+  test,
+  request
+} = require('./../../common.js');
+
+const PORT = 3012;
+const STATS_PORT = 3014;
+
+const sleep = ms => new Promise(resolve => setTimeout(resolve, ms));
+
+// This app gets its own process, since the tests read what it logs
+function startApp() {
+  const target = process.argv[2] || 'debug';
+  const osgood = path.join(__dirname, '..', '..', '..', '..', 'target', target, 'osgood');
+  const app = spawn(osgood, [path.join(__dirname, '..', 'app-pool.js')], {
+    stdio: 'pipe'
+  });
+  app.output = '';
+  app.stdout.on('data', data => app.output += data);
+  app.stderr.on('data', data => app.output += data);
+  app.exited = new Promise(resolve => app.on('exit', resolve));
+  return app;
+}
+
+test(async function fetchesReuseConnections() {
+  const app = startApp();
+  await sleep(300);
+
+  try {
+    const [res, body] = await request(PORT, '/fetch-three');
+    assert.strictEqual(body.toString(), 'done');
+    await sleep(300);
+    assert.ok(app.output.includes(
+      'Outbound http pool: 3 requests, 0 in flight, 1 connections opened, 1 open'
+    ), app.output);
+  } finally {
+    app.kill('SIGTERM');
+    await app.exited;
+  }
+});
+
+test(async function statsEndpoint() {
+  const app = startApp();
+  await sleep(300);
+
+  try {
+    await request(PORT, '/fetch-three');
+    const [res, body] = await request(STATS_PORT, '/stats');
+    assert.strictEqual(res.statusCode, 200);
+    assert.strictEqual(res.headers['content-type'], 'application/json');
+    const stats = JSON.parse(body.toString());
+    assert.deepStrictEqual(stats.outboundPools.http, {
+      requests: 3,
+      inFlight: 0,
+      connectionsOpened: 1,
+      connectionsOpen: 1
+    });
+    assert.strictEqual(stats.outboundPools.https.requests, 0);
+    const [notFound] = await request(STATS_PORT, '/');
+    assert.strictEqual(notFound.statusCode, 404);
+  } finally {
+    app.kill('SIGTERM');
+    await app.exited;
+  }
+});
+
+test(async function reportedPolicyViolations() {
+  const app = startApp();
+  await sleep(300);
+
+  try {
+    const [res, body] = await request(PORT, '/report');
+    assert.strictEqual(body.toString(), 'done');
+    await sleep(300);
+    const events = app.output
+      .split('\n')
+      .filter(line => line.includes('Policy violation: '))
+      .map(line => JSON.parse(line.split('Policy violation: ')[1]));
+    assert.strictEqual(events.length, 3);
+    assert.strictEqual(events[0].event, 'policy_violation');
+    assert.strictEqual(events[0].route, 'GET localhost/report');
+    assert.strictEqual(events[0].method, 'GET');
+    assert.strictEqual(events[0].url, 'http://localhost:9001/');
+    assert.strictEqual(events[0].hostHeader, null);
+    assert.strictEqual(events[0].mode, 'report');
+    assert.ok(Number.isInteger(events[0].timestamp));
+    assert.ok(app.output.includes('Outbound policy violations: 3'), app.output);
+    const [, stats] = await request(STATS_PORT, '/stats');
+    assert.deepStrictEqual(JSON.parse(stats.toString()).routes, {
+      'GET localhost/report': { policyViolations: 3, restarts: 0 }
+    });
+  } finally {
+    app.kill('SIGTERM');
+    await app.exited;
+  }
+});
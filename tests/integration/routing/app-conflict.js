@@ -0,0 +1,4 @@
+app.port = 3010;
+
+app.get('/users/:id', 'params.js');
+app.get('/users/:name', 'params.js');
@@ -0,0 +1,8 @@
+app.port = 3009;
+
+// Declared from least to most specific, to check that the order doesn't matter
+app.get('/files/**', 'params.js');
+app.get('/files/:name/**', 'params.js');
+app.get('/files/:name', 'params.js');
+app.get('/files/:name.json', 'params.js');
+app.get('/files/latest', 'params.js');
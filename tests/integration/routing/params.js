@@ -0,0 +1 @@
+export default (req, context) => context.params;
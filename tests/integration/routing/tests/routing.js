@@ -0,0 +1,47 @@
+'use strict';
+const assert = require('assert');
+const path = require('path');
+const { spawn } = require('child_process');
+const {
+  test,
+  request
+} = require('./../../common.js');
+
+const PORT = 3009;
+
+async function params(route) {
+  const [res, body] = await request(PORT, route);
+  assert.strictEqual(res.statusCode, 200);
+  return JSON.parse(body.toString());
+}
+
+test(async function staticBeatsParams() {
+  assert.deepStrictEqual(await params('/files/latest'), {});
+});
+
+test(async function paramsWithText() {
+  assert.deepStrictEqual(await params('/files/report.json'), { name: 'report' });
+});
+
+test(async function paramBeatsCatchAll() {
+  assert.deepStrictEqual(await params('/files/report'), { name: 'report' });
+});
+
+test(async function catchAll() {
+  assert.deepStrictEqual(await params('/files/report/2019/may'), { name: 'report' });
+  assert.deepStrictEqual(await params('/files/'), {});
+});
+
+test(async function conflictingRoutesFailAtStartup() {
+  const target = process.argv[2] || 'debug';
+  const osgood = path.join(__dirname, '..', '..', '..', '..', 'target', target, 'osgood');
+  const app = spawn(osgood, [path.join(__dirname, '..', 'app-conflict.js')], {
+    stdio: 'pipe'
+  });
+  let output = '';
+  app.stdout.on('data', data => output += data);
+  app.stderr.on('data', data => output += data);
+  const status = await new Promise(resolve => app.on('exit', resolve));
+  assert.strictEqual(status, 1);
+  assert.ok(output.includes('Route GET /users/:name conflicts with GET /users/:id'));
+});
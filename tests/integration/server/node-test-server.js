@@ -65,10 +65,27 @@ http.createServer((req, res) => {
   }
 
   if (req.url === '/echo' && req.method === 'POST') {
+    if (req.headers['content-type']) {
+      res.setHeader('Content-Type', req.headers['content-type']);
+    }
     req.pipe(res);
     return;
   }
 
+  if (req.url === '/echo-headers') {
+    res.setHeader('Content-Type', 'application/json');
+    res.end(JSON.stringify(req.headers));
+    return;
+  }
+
+  if (req.url === '/multi-headers') {
+    res.setHeader('Set-Cookie', ['a=1; Expires=Wed, 21 Oct 2037 07:28:00 GMT', 'b=2']);
+    res.setHeader('Via', ['1.1 first', '1.1 second']);
+    res.setHeader('X-Latin', 'caf\u00e9');
+    res.end('ok');
+    return;
+  }
+
   if (req.url === '/image.png') {
     res.writeHead(200, {
       'Content-Type': 'image/png'
@@ -77,6 +94,34 @@ http.createServer((req, res) => {
     return;
   }
 
+  const redirects = {
+    '/redirect': '/',
+    '/redirect-forbidden': '/image.png',
+    '/redirect-loop': '/redirect-loop',
+    '/redirect-cross-origin': 'http://127.0.0.1:9001/echo-headers'
+  };
+  if (redirects[req.url]) {
+    res.writeHead(302, {
+      'Location': redirects[req.url]
+    });
+    res.end();
+    return;
+  }
+
+  if (req.url === '/multiple-choices') {
+    // Not a redirect, despite the Location header
+    res.writeHead(300, {
+      'Location': '/'
+    });
+    res.end('choose');
+    return;
+  }
+
+  if (req.url === '/slow') {
+    // Never answers, so that clients have to give up on it
+    return;
+  }
+
   if (req.url === '/?query=test') {
     res.writeHead(200, {
       'Content-Type': 'text/plain'
@@ -0,0 +1,4 @@
+app.port = 3008;
+app.shutdownTimeout = 5000;
+
+app.get('/slow', 'slow.js');
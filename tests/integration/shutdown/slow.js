@@ -0,0 +1,4 @@
+export default () =>
+  new Promise(resolve => {
+    setTimeout(() => resolve('finished'), 500);
+  });
@@ -0,0 +1,36 @@
+'use strict';
+const assert = require('assert');
+const path = require('path');
+const { spawn } = require('child_process');
+const {
+  test,
+  request
+} = require('./../../common.js');
+
+const PORT = 3008;
+
+const sleep = ms => new Promise(resolve => setTimeout(resolve, ms));
+
+// This app gets its own process, since the test shuts it down
+function startApp() {
+  const target = process.argv[2] || 'debug';
+  const osgood = path.join(__dirname, '..', '..', '..', '..', 'target', target, 'osgood');
+  return spawn(osgood, [path.join(__dirname, '..', 'app-shutdown.js')], {
+    stdio: 'pipe'
+  });
+}
+
+test(async function shutdownFinishesInFlightRequests() {
+  const app = startApp();
+  const exited = new Promise(resolve => app.on('exit', resolve));
+  await sleep(300);
+
+  const response = request(PORT, '/slow');
+  await sleep(100);
+  app.kill('SIGTERM');
+
+  const [res, body] = await response;
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(body.toString(), 'finished');
+  assert.strictEqual(await exited, 0);
+});
@@ -0,0 +1,7 @@
+app.port = 3005;
+app.tls = {
+  cert: 'cert.pem',
+  key: 'key.pem'
+};
+
+app.get('/url', 'url.js');
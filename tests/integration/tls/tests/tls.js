@@ -0,0 +1,25 @@
+'use strict';
+const assert = require('assert');
+const {
+  test,
+  request
+} = require('./../../common.js');
+
+const PORT = 3005;
+
+test(async function tlsUrl() {
+  const [res, body] = await request(PORT, `https://localhost:${PORT}/url`, {
+    rejectUnauthorized: false
+  });
+  assert.strictEqual(res.statusCode, 200);
+  assert.strictEqual(body.toString(), `https://localhost:${PORT}/url`);
+});
+
+test(async function tlsRejectsPlainHttp() {
+  try {
+    await request(PORT, '/url');
+  } catch (e) {
+    return;
+  }
+  throw new Error('plain HTTP request should have failed');
+});
@@ -0,0 +1 @@
+export default (request) => request.url;
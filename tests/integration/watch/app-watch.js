@@ -0,0 +1,4 @@
+app.port = 3007;
+
+app.get('/worker', 'worker.js');
+app.get('/module', 'module.js');
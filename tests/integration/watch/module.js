@@ -0,0 +1,3 @@
+import { MESSAGE } from './message.js';
+
+export default () => MESSAGE;
@@ -0,0 +1,52 @@
+'use strict';
+const assert = require('assert');
+const fs = require('fs');
+const path = require('path');
+const {
+  test,
+  request
+} = require('./../../common.js');
+
+const PORT = 3007;
+
+const sleep = ms => new Promise(resolve => setTimeout(resolve, ms));
+
+// Waits for the watcher to pick up a change, which it checks for every 500ms
+async function waitForBody(url, expected) {
+  let body;
+  for (let i = 0; i < 30; i++) {
+    [, body] = await request(PORT, url);
+    if (body.toString() === expected) {
+      return;
+    }
+    await sleep(100);
+  }
+  assert.strictEqual(body.toString(), expected);
+}
+
+async function withChangedFile(file, contents, fn) {
+  const filename = path.join(__dirname, '..', file);
+  const original = fs.readFileSync(filename, 'utf8');
+  fs.writeFileSync(filename, contents);
+  try {
+    await fn();
+  } finally {
+    fs.writeFileSync(filename, original);
+  }
+}
+
+test(async function watchWorkerFile() {
+  await waitForBody('/worker', 'original worker');
+  await withChangedFile('worker.js', "export default () => 'changed worker';\n", async () => {
+    await waitForBody('/worker', 'changed worker');
+  });
+});
+
+test(async function watchImportedModule() {
+  await waitForBody('/module', 'original module');
+  // Give the watcher a chance to notice the imported module
+  await sleep(600);
+  await withChangedFile('message.js', "export const MESSAGE = 'changed module';\n", async () => {
+    await waitForBody('/module', 'changed module');
+  });
+});
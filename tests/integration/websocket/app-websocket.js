@@ -0,0 +1,3 @@
+app.port = 3011;
+
+app.websocket('/rooms/:room', 'room.js');
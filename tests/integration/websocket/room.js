@@ -0,0 +1,19 @@
+export default async (socket, context) => {
+  socket.send(`joined ${context.params.room}`);
+  for await (const message of socket) {
+    if (message === 'bye') {
+      socket.close(1000, 'bye');
+    } else if (message === 'cookies') {
+      try {
+        context.cookies.set('room', context.params.room);
+        socket.send('cookie set');
+      } catch (e) {
+        socket.send(e.message);
+      }
+    } else if (typeof message === 'string') {
+      socket.send(`echo: ${message}`);
+    } else {
+      socket.send(new Uint8Array(message).reverse());
+    }
+  }
+};
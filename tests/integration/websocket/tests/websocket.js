@@ -0,0 +1,43 @@
+'use strict';
+const assert = require('assert');
+const {
+  test,
+  request,
+  websocket
+} = require('./../../common.js');
+
+const PORT = 3011;
+
+test(async function websocketMessages() {
+  const client = await websocket(PORT, '/rooms/lobby');
+  let frame = await client.receive();
+  assert.strictEqual(frame.opcode, 0x1);
+  assert.strictEqual(frame.data.toString(), 'joined lobby');
+
+  client.send('hello');
+  frame = await client.receive();
+  assert.strictEqual(frame.opcode, 0x1);
+  assert.strictEqual(frame.data.toString(), 'echo: hello');
+
+  client.send('cookies');
+  frame = await client.receive();
+  assert.strictEqual(frame.data.toString(), 'Cookies can\'t be set on WebSocket connections');
+
+  client.send(Buffer.from([1, 2, 3]));
+  frame = await client.receive();
+  assert.strictEqual(frame.opcode, 0x2);
+  assert.deepStrictEqual([...frame.data], [3, 2, 1]);
+
+  client.send('bye');
+  frame = await client.receive();
+  assert.strictEqual(frame.opcode, 0x8);
+  assert.strictEqual(frame.data.readUInt16BE(0), 1000);
+  assert.strictEqual(frame.data.slice(2).toString(), 'bye');
+  client.close();
+});
+
+test(async function websocketRequiresUpgrade() {
+  const [res, body] = await request(PORT, '/rooms/lobby');
+  assert.strictEqual(res.statusCode, 426);
+  assert.strictEqual(res.headers['upgrade'], 'websocket');
+});